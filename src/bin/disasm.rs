@@ -0,0 +1,53 @@
+use rust_8080::debugger::parse_number;
+use rust_8080::disasm;
+use std::env;
+use std::fs;
+use std::process;
+
+const USAGE: &str = "\
+usage: disasm [-a ADDR] FILE
+
+Prints an Intel mnemonic listing of a ROM or program image.
+
+options:
+  -a, --load-addr ADDR    address of the first byte (default $0000)
+  -h, --help              show this help
+
+Numbers take a 0x or $ prefix for hex.";
+
+fn main() {
+    let mut origin: u16 = 0;
+    let mut file = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "-a" | "--load-addr" => {
+                let value = args.next().unwrap_or_default();
+                origin = parse_number(&value)
+                    .ok()
+                    .and_then(|addr| u16::try_from(addr).ok())
+                    .unwrap_or_else(|| usage_error(&format!("'{}' is not an address", value)));
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 => usage_error(&format!("unknown option '{}'", arg)),
+            _ if file.is_none() => file = Some(arg),
+            _ => usage_error(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let file = file.unwrap_or_else(|| usage_error("no file given"));
+    let data = fs::read(&file).unwrap_or_else(|e| {
+        eprintln!("{}: {}", file, e);
+        process::exit(1);
+    });
+    for instruction in disasm::disassemble_image(&data, origin) {
+        println!("{}", instruction);
+    }
+}
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("{}\n\n{}", message, USAGE);
+    process::exit(2);
+}
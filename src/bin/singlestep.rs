@@ -0,0 +1,59 @@
+use rust_8080::singlestep::{self, Report};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "\
+usage: singlestep FILE|DIR...
+
+Runs single instruction test vectors, JSON arrays of cases with initial and
+final registers, flags and RAM and the cycle count. Directories are searched
+for .json files. Fields that differ are reported grouped by opcode.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return;
+    }
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+    let mut files = Vec::new();
+    for arg in &args {
+        collect(Path::new(arg), &mut files);
+    }
+    let mut report = Report::new();
+    for file in &files {
+        let cases = singlestep::load(file).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+        for case in &cases {
+            report.run(case);
+        }
+    }
+    println!("{}", report);
+    if report.failures() > 0 {
+        process::exit(1);
+    }
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return;
+    }
+    let entries = fs::read_dir(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+    let mut found: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    found.sort();
+    files.extend(found);
+}
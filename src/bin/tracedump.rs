@@ -0,0 +1,38 @@
+use rust_8080::trace::TraceReader;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "\
+usage: tracedump FILE
+
+Prints a binary trace written with --trace-format binary in the text format.";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let path = match args.as_slice() {
+        [flag] if flag == "-h" || flag == "--help" => {
+            println!("{}", USAGE);
+            return;
+        }
+        [path] => path,
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = dump(path) {
+        eprintln!("{}: {}", path, e);
+        process::exit(1);
+    }
+}
+
+fn dump(path: &str) -> io::Result<()> {
+    let reader = TraceReader::new(BufReader::new(File::open(path)?))?;
+    let mut out = BufWriter::new(io::stdout().lock());
+    for info in reader {
+        writeln!(out, "{}", info?)?;
+    }
+    out.flush()
+}
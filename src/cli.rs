@@ -0,0 +1,406 @@
+use rust_8080::debugger::parse_number;
+use rust_8080::memory::UnmappedPolicy;
+use rust_8080::report::DumpFormat;
+use rust_8080::trace::{TraceFilter, TraceFormat};
+use rust_8080::watch::Watchpoint;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: rust_8080 [options] [ROM]
+
+ROM is a directory holding a ROM set, a zip archive of one, or a single
+binary image. Space Invaders looks for invaders.e-h in the current
+directory when ROM is omitted.
+
+options:
+  -m, --machine NAME          invaders (default), cpm or bare
+  -a, --load-addr ADDR        load address of a single binary
+                              (default $0000, $0100 for cpm)
+  -s, --scale N               window scale 1, 2, 4 or 8 (default 1)
+      --speed FACTOR          emulation speed multiplier (default 1.0)
+      --headless              run without opening a window
+  -n, --max-instructions N    stop after N instructions
+      --strict                treat undocumented opcodes as illegal
+      --stack FROM-TO         stop when SP leaves this range
+      --ram KIB               RAM of the bare machine from $0000
+                              (default 64)
+      --unmapped POLICY       accesses to unmapped memory: open-bus
+                              (reads $ff, default), mirror or trap (stop
+                              in the debugger, or exit without one);
+                              replaces the board's mirroring for invaders
+      --frames N              stop after N frames
+      --capture FRAME,...     save the screen as PNG after these frames,
+                              F10 saves it at any time
+      --capture-dir DIR       where screens are saved (default .)
+      --config FILE           key bindings and DIP switches
+                              (default invaders.cfg if it exists)
+      --samples DIR           directory with 0.wav-9.wav (default samples),
+                              sound is only played by builds with
+                              `--features audio`, others are silent
+  -d, --debug                 start paused in the debugger
+  -b, --break ADDR            stop in the debugger at ADDR (repeatable)
+  -w, --watch SPEC            stop in the debugger on a memory access
+                              (repeatable), SPEC is ADDR[-END] followed by
+                              ,r ,w (default) or ,rw  ,=VALUE and ,log
+      --gdb PORT              wait for a GDB client on 127.0.0.1:PORT
+      --trace FILE            write an instruction trace to FILE
+      --trace-format FORMAT   text (default) or binary
+      --trace-pc FROM-TO      only trace instructions at these addresses
+      --trace-window FROM-TO  only trace these instruction numbers
+      --trace-opcode OP,...   only trace these opcodes
+      --crash-report FILE     where to write the report when emulation
+                              aborts (default crash-report.txt)
+      --dump-format FORMAT    memory in reports: hexdump (default), ihex
+                              or raw
+      --dump-range FROM-TO    memory range in reports (default all)
+      --history N             instructions listed in reports (default 32)
+      --state-dir DIR         where F5 saves and F9 loads the selected
+                              slot, F6/F7 select slots 0-9 (default states)
+      --load-state FILE       start from a save state
+      --rewind-memory MIB     memory for rewinding with F8 held
+                              (default 16, 0 disables rewinding)
+      --record-movie FILE     record the inputs of every frame to FILE
+      --play-movie FILE       replay a movie and check the final state
+      --compare FILE          run in lockstep with a reference log of the
+                              registers before every instruction and stop
+                              at the first mismatch
+  -h, --help                  show this help
+
+Numbers take a 0x or $ prefix for hex.
+
+exit status:
+  0  success           1  error              2  bad usage
+  3  illegal opcode    4  unmapped memory    5  stack overflow/underflow
+  6  I/O device error  7  ROM loading error  8  halted with interrupts off";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Machine {
+    SpaceInvaders,
+    /// A CP/M .COM program at $0100 on 64 KiB of RAM.
+    Cpm,
+    /// 64 KiB of RAM, no devices.
+    Bare,
+}
+
+pub struct Options {
+    pub machine: Machine,
+    pub rom: Option<PathBuf>,
+    pub load_addr: Option<u16>,
+    pub scale: usize,
+    pub speed: f64,
+    pub headless: bool,
+    pub max_instructions: Option<u64>,
+    /// Undocumented opcodes are errors instead of aliases.
+    pub strict: bool,
+    pub stack: Option<RangeInclusive<u16>>,
+    /// Bytes of RAM of the bare machine.
+    pub ram: usize,
+    pub unmapped: Option<UnmappedPolicy>,
+    pub frames: Option<u64>,
+    /// Frames after which the screen is saved, counted from the start of the run.
+    pub capture: Vec<u64>,
+    pub capture_dir: PathBuf,
+    /// Reference log to run in lockstep with, see `lockstep::Expected::parse`.
+    pub compare: Option<PathBuf>,
+    pub config: Option<PathBuf>,
+    pub samples: PathBuf,
+    pub debug: bool,
+    pub breakpoints: Vec<u16>,
+    pub watchpoints: Vec<Watchpoint>,
+    pub gdb_port: Option<u16>,
+    pub trace: Option<PathBuf>,
+    pub trace_format: TraceFormat,
+    pub trace_filter: TraceFilter,
+    pub crash_report: PathBuf,
+    pub dump_format: DumpFormat,
+    pub dump_range: RangeInclusive<u16>,
+    pub history: usize,
+    pub state_dir: PathBuf,
+    pub load_state: Option<PathBuf>,
+    /// Bytes of snapshots kept for rewinding.
+    pub rewind_memory: usize,
+    pub record_movie: Option<PathBuf>,
+    pub play_movie: Option<PathBuf>,
+    pub help: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            machine: Machine::SpaceInvaders,
+            rom: None,
+            load_addr: None,
+            scale: 1,
+            speed: 1.0,
+            headless: false,
+            max_instructions: None,
+            strict: false,
+            stack: None,
+            ram: 0x10000,
+            unmapped: None,
+            frames: None,
+            capture: Vec::new(),
+            capture_dir: PathBuf::from("."),
+            compare: None,
+            config: None,
+            samples: PathBuf::from("samples"),
+            debug: false,
+            breakpoints: Vec::new(),
+            watchpoints: Vec::new(),
+            gdb_port: None,
+            trace: None,
+            trace_format: TraceFormat::Text,
+            trace_filter: TraceFilter::default(),
+            crash_report: PathBuf::from("crash-report.txt"),
+            dump_format: DumpFormat::Hexdump,
+            dump_range: 0x0000..=0xffff,
+            history: 32,
+            state_dir: PathBuf::from("states"),
+            load_state: None,
+            rewind_memory: 16 << 20,
+            record_movie: None,
+            play_movie: None,
+            help: false,
+        }
+    }
+}
+
+impl Options {
+    /// Load address of a single binary for the selected machine.
+    pub fn load_addr(&self) -> u16 {
+        match (self.load_addr, self.machine) {
+            (Some(addr), _) => addr,
+            (None, Machine::Cpm) => 0x0100,
+            (None, _) => 0x0000,
+        }
+    }
+
+    /// Whether to run under the debugger.
+    pub fn debugger(&self) -> bool {
+        self.debug || !self.breakpoints.is_empty() || !self.watchpoints.is_empty() || self.gdb_port.is_some()
+    }
+}
+
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} needs a value", flag))
+        };
+        match flag.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-m" | "--machine" => {
+                options.machine = match value()?.as_str() {
+                    "invaders" => Machine::SpaceInvaders,
+                    "cpm" => Machine::Cpm,
+                    "bare" => Machine::Bare,
+                    other => return Err(format!("unknown machine '{}'", other)),
+                }
+            }
+            "-a" | "--load-addr" => {
+                let addr = parse_number(&value()?)?;
+                options.load_addr = Some(u16::try_from(addr).map_err(|_| format!("load address {:#x} is above $ffff", addr))?);
+            }
+            "-s" | "--scale" => {
+                options.scale = match parse_number(&value()?)? {
+                    scale @ (1 | 2 | 4 | 8) => scale as usize,
+                    other => return Err(format!("scale must be 1, 2, 4 or 8, got {}", other)),
+                }
+            }
+            "--speed" => {
+                let speed = value()?;
+                options.speed = match speed.parse::<f64>() {
+                    Ok(speed) if speed > 0.0 && speed.is_finite() => speed,
+                    _ => return Err(format!("speed must be a positive number, got '{}'", speed)),
+                }
+            }
+            "--headless" => options.headless = true,
+            "-n" | "--max-instructions" => options.max_instructions = Some(parse_number(&value()?)?),
+            "--strict" => options.strict = true,
+            "--stack" => options.stack = Some(parse_addr_range(&value()?)?),
+            "--ram" => {
+                options.ram = match parse_number(&value()?)? {
+                    kib @ 1..=64 => kib as usize * 1024,
+                    other => return Err(format!("RAM must be 1 to 64 KiB, got {}", other)),
+                }
+            }
+            "--unmapped" => {
+                options.unmapped = Some(match value()?.as_str() {
+                    "open-bus" => UnmappedPolicy::OpenBus,
+                    "mirror" => UnmappedPolicy::Mirror,
+                    "trap" => UnmappedPolicy::Trap,
+                    other => return Err(format!("unknown unmapped policy '{}'", other)),
+                })
+            }
+            "--frames" => options.frames = Some(parse_number(&value()?)?),
+            "--capture" => {
+                for frame in value()?.split(',') {
+                    options.capture.push(parse_number(frame.trim())?);
+                }
+            }
+            "--capture-dir" => options.capture_dir = PathBuf::from(value()?),
+            "--compare" => options.compare = Some(PathBuf::from(value()?)),
+            "--config" => options.config = Some(PathBuf::from(value()?)),
+            "--samples" => options.samples = PathBuf::from(value()?),
+            "-d" | "--debug" => options.debug = true,
+            "-b" | "--break" => {
+                let addr = parse_number(&value()?)?;
+                options.breakpoints.push(u16::try_from(addr).map_err(|_| format!("breakpoint {:#x} is above $ffff", addr))?);
+            }
+            "-w" | "--watch" => options.watchpoints.push(Watchpoint::parse(&value()?, parse_number)?),
+            "--gdb" => {
+                let port = parse_number(&value()?)?;
+                options.gdb_port = Some(u16::try_from(port).map_err(|_| format!("{} is not a TCP port", port))?);
+            }
+            "--trace" => options.trace = Some(PathBuf::from(value()?)),
+            "--trace-format" => {
+                options.trace_format = match value()?.as_str() {
+                    "text" => TraceFormat::Text,
+                    "binary" => TraceFormat::Binary,
+                    other => return Err(format!("unknown trace format '{}'", other)),
+                }
+            }
+            "--trace-pc" => options.trace_filter.pc = Some(parse_addr_range(&value()?)?),
+            "--trace-window" => options.trace_filter.window = Some(parse_range(&value()?)?),
+            "--trace-opcode" => {
+                for opcode in value()?.split(',') {
+                    let opcode = parse_number(opcode.trim())?;
+                    options
+                        .trace_filter
+                        .opcodes
+                        .push(u8::try_from(opcode).map_err(|_| format!("opcode {:#x} is above $ff", opcode))?);
+                }
+            }
+            "--crash-report" => options.crash_report = PathBuf::from(value()?),
+            "--dump-format" => {
+                options.dump_format = match value()?.as_str() {
+                    "hexdump" => DumpFormat::Hexdump,
+                    "ihex" => DumpFormat::IntelHex,
+                    "raw" => DumpFormat::Raw,
+                    other => return Err(format!("unknown dump format '{}'", other)),
+                }
+            }
+            "--dump-range" => options.dump_range = parse_addr_range(&value()?)?,
+            "--history" => options.history = parse_number(&value()?)? as usize,
+            "--state-dir" => options.state_dir = PathBuf::from(value()?),
+            "--load-state" => options.load_state = Some(PathBuf::from(value()?)),
+            "--rewind-memory" => {
+                let mib = parse_number(&value()?)?;
+                options.rewind_memory = usize::try_from(mib)
+                    .ok()
+                    .and_then(|mib| mib.checked_mul(1 << 20))
+                    .ok_or_else(|| format!("rewind memory of {} MiB is too large", mib))?;
+            }
+            "--record-movie" => options.record_movie = Some(PathBuf::from(value()?)),
+            "--play-movie" => options.play_movie = Some(PathBuf::from(value()?)),
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ if options.rom.is_none() => options.rom = Some(PathBuf::from(arg)),
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    if options.rom.is_none() && options.machine != Machine::SpaceInvaders && !options.help {
+        return Err("this machine needs a ROM or program file".to_string());
+    }
+    if options.ram != 0x10000 && options.machine != Machine::Bare {
+        return Err("--ram is only supported for bare".to_string());
+    }
+    if options.load_state.is_some() && options.machine != Machine::SpaceInvaders {
+        return Err("save states are only supported for invaders".to_string());
+    }
+    if (options.record_movie.is_some() || options.play_movie.is_some()) && options.machine != Machine::SpaceInvaders {
+        return Err("movies are only supported for invaders".to_string());
+    }
+    if options.play_movie.is_some() && (options.record_movie.is_some() || options.load_state.is_some()) {
+        return Err("--play-movie cannot be combined with --record-movie or --load-state".to_string());
+    }
+    Ok(options)
+}
+
+/// Parses an inclusive `FROM-TO` range of numbers.
+fn parse_range(text: &str) -> Result<RangeInclusive<u64>, String> {
+    let (start, end) = text
+        .split_once('-')
+        .ok_or_else(|| format!("'{}' is not a FROM-TO range", text))?;
+    let (start, end) = (parse_number(start.trim())?, parse_number(end.trim())?);
+    if start > end {
+        return Err(format!("range '{}' is empty", text));
+    }
+    Ok(start..=end)
+}
+
+/// Parses an inclusive `FROM-TO` range of addresses.
+fn parse_addr_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let range = parse_range(text)?;
+    let (start, end) = (*range.start(), *range.end());
+    if end > 0xffff {
+        return Err(format!("address {:#x} is above $ffff", end));
+    }
+    Ok(start as u16..=end as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &str) -> Result<Options, String> {
+        parse(args.split_whitespace().map(String::from))
+    }
+
+    fn error(args: &str) -> String {
+        parse_args(args).err().expect("the arguments are invalid")
+    }
+
+    #[test]
+    fn ram_only_for_bare() {
+        assert_eq!(parse_args("-m bare --ram 16 prog.bin").unwrap().ram, 16 * 1024);
+        assert_eq!(parse_args("-m bare --ram=64 prog.bin").unwrap().ram, 0x10000);
+        assert_eq!(error("--ram 16"), "--ram is only supported for bare");
+        assert_eq!(error("-m cpm --ram 16 prog.com"), "--ram is only supported for bare");
+        assert_eq!(error("-m bare --ram 0 prog.bin"), "RAM must be 1 to 64 KiB, got 0");
+        assert_eq!(error("-m bare --ram 65 prog.bin"), "RAM must be 1 to 64 KiB, got 65");
+    }
+
+    #[test]
+    fn movie_playback_excludes_recording() {
+        assert!(parse_args("--play-movie run.mov").is_ok());
+        assert_eq!(
+            error("--play-movie run.mov --record-movie new.mov"),
+            "--play-movie cannot be combined with --record-movie or --load-state"
+        );
+        assert_eq!(
+            error("-m bare --play-movie run.mov prog.bin"),
+            "movies are only supported for invaders"
+        );
+    }
+
+    #[test]
+    fn rewind_memory_in_mib() {
+        assert_eq!(parse_args("--rewind-memory 64").unwrap().rewind_memory, 64 << 20);
+        assert_eq!(parse_args("--rewind-memory 0").unwrap().rewind_memory, 0);
+        let huge = u64::MAX >> 10;
+        assert_eq!(
+            error(&format!("--rewind-memory {}", huge)),
+            format!("rewind memory of {} MiB is too large", huge)
+        );
+        assert_eq!(error("--rewind-memory lots"), "'lots' is not a number");
+    }
+
+    #[test]
+    fn scale_is_a_power_of_two() {
+        for scale in [1, 2, 4, 8] {
+            assert_eq!(parse_args(&format!("--scale {}", scale)).unwrap().scale, scale);
+        }
+        assert_eq!(error("-s 3"), "scale must be 1, 2, 4 or 8, got 3");
+        assert_eq!(error("--scale=16"), "scale must be 1, 2, 4 or 8, got 16");
+        assert_eq!(error("--scale"), "--scale needs a value");
+    }
+}
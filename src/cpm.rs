@@ -0,0 +1,103 @@
+use crate::cpu::{Cpu8080, EmulationError};
+use crate::io::IoBus;
+use crate::memory::{Bus, MemoryMap};
+use std::io::{self, Write};
+
+/// Programs call the BDOS here with the function number in C.
+pub const BDOS: u16 = 0x0005;
+/// Start of the transient program area, where .COM files are loaded and run.
+pub const TPA: u16 = 0x0100;
+/// Top of the TPA, read by programs from $0006 to set up their stack.
+const BDOS_BASE: u16 = 0xfe00;
+
+#[derive(Debug)]
+pub enum Exit {
+    /// The program jumped to $0000 or called BDOS function 0.
+    WarmBoot,
+    /// HLT with interrupts disabled, at this address.
+    Halted(u16),
+    /// The instruction limit was reached.
+    Limit,
+    /// The CPU could not execute an instruction.
+    Aborted(EmulationError),
+}
+
+/// Outcome of a program run by `run`.
+#[derive(Debug)]
+pub struct Run {
+    pub exit: Exit,
+    /// Everything written to the console.
+    pub output: String,
+    pub instructions: u64,
+}
+
+/// Sets up the zero page: HLT at the warm boot vector and a JMP to the top of the
+/// TPA at `BDOS`, so programs find the end of memory at $0006.
+pub fn install<M: Bus>(memory: &mut M) {
+    memory.write(0x0000, 0x76);
+    let [low, high] = BDOS_BASE.to_le_bytes();
+    for (addr, value) in (BDOS..).zip([0xc3, low, high]) {
+        memory.write(addr, value);
+    }
+    memory.write(BDOS_BASE, 0xc9);
+}
+
+/// Carries out the BDOS call the CPU is about to make at `BDOS` and returns to the
+/// caller. Console output (function 2) and strings ending in `$` (function 9) go to
+/// `out`, function 0 warm boots and the others do nothing.
+pub fn bdos<M: Bus, I: IoBus, W: Write>(cpu: &mut Cpu8080<M, I>, out: &mut W) -> io::Result<()> {
+    let sp = cpu.sp();
+    let ret = u16::from_le_bytes([cpu.memory().peek(sp), cpu.memory().peek(sp.wrapping_add(1))]);
+    cpu.set_sp(sp.wrapping_add(2));
+    cpu.set_pc(ret);
+    match cpu.c() {
+        0 => cpu.set_pc(0x0000),
+        2 => out.write_all(&[cpu.e()])?,
+        9 => {
+            let mut addr = cpu.de();
+            let mut text = Vec::new();
+            while cpu.memory().peek(addr) != b'$' && text.len() < 0x10000 {
+                text.push(cpu.memory().peek(addr));
+                addr = addr.wrapping_add(1);
+            }
+            out.write_all(&text)?;
+        }
+        _ => {}
+    }
+    out.flush()
+}
+
+/// Runs a .COM program on 64 KiB of RAM until it warm boots, halts or has executed
+/// `max_instructions`, capturing its console output.
+pub fn run(program: &[u8], max_instructions: u64) -> Run {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    install(cpu.memory_mut());
+    cpu.memory_mut().load(TPA, program);
+    cpu.set_pc(TPA);
+    let mut output = Vec::new();
+    let mut instructions = 0;
+    let exit = loop {
+        match cpu.pc() {
+            0x0000 => break Exit::WarmBoot,
+            BDOS => {
+                bdos(&mut cpu, &mut output).expect("writing to a Vec does not fail");
+                continue;
+            }
+            _ => {}
+        }
+        if instructions >= max_instructions {
+            break Exit::Limit;
+        }
+        match cpu.step() {
+            Ok(_) => {}
+            Err(EmulationError::HaltDeadlock { pc }) => break Exit::Halted(pc),
+            Err(error) => break Exit::Aborted(error),
+        }
+        instructions += 1;
+    };
+    Run {
+        exit,
+        output: String::from_utf8_lossy(&output).into_owned(),
+        instructions,
+    }
+}
@@ -1,5 +1,10 @@
+pub mod cpu8080;
+pub mod error;
 pub mod instructions;
+pub mod opcodes;
 pub mod state8080;
 pub mod debugging;
+pub use self::cpu8080::Cpu8080;
+pub use self::error::EmulationError;
 pub use self::state8080::ConditionCodes;
-//pub struct State8080;
\ No newline at end of file
+pub use self::state8080::State8080;
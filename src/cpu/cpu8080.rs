@@ -0,0 +1,1554 @@
+use crate::cpu::error::EmulationError;
+use crate::cpu::instructions;
+use crate::cpu::opcodes;
+use crate::cpu::state8080::{ConditionCodes, State8080};
+use crate::io::{IoBus, IoMap};
+use crate::memory::{Bus, MemoryMap};
+use crate::savestate::{Input, Snapshot, StateError};
+use std::ops::RangeInclusive;
+
+/// An Intel 8080 together with the memory bus and the I/O devices wired to IN/OUT.
+pub struct Cpu8080<M: Bus = MemoryMap, I: IoBus = IoMap> {
+    state: State8080,
+    memory: M,
+    io: I,
+    undocumented: bool,
+    stack: Option<RangeInclusive<u16>>,
+}
+
+impl Default for Cpu8080 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Cpu8080 {
+    /// A CPU with 64 KiB of flat RAM.
+    pub fn new() -> Self {
+        Self::with_memory(MemoryMap::new())
+    }
+}
+
+impl<M: Bus> Cpu8080<M> {
+    /// A CPU on the given memory bus with no I/O devices attached.
+    pub fn with_memory(memory: M) -> Self {
+        Self::with_devices(memory, IoMap::new())
+    }
+}
+
+impl<M: Bus, I: IoBus> Cpu8080<M, I> {
+    pub fn with_devices(memory: M, io: I) -> Self {
+        let condition: ConditionCodes = ConditionCodes {
+            z: false,
+            s: false,
+            p: false,
+            cy: false,
+            ac: false,
+            pad: 0,
+        };
+        let state: State8080 = State8080 {
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            sp: 0xF000,
+            pc: 0,
+            cc: condition,
+            int_enable: false,
+            int_delay: false,
+            halted: false,
+            interrupt_request: None,
+        };
+        Cpu8080 {
+            state,
+            memory,
+            io,
+            undocumented: true,
+            stack: None,
+        }
+    }
+
+    /// Whether the undocumented opcodes run as their documented twins, as on the
+    /// real chip, or are refused as illegal. Allowed by default.
+    pub fn set_undocumented(&mut self, allowed: bool) {
+        self.undocumented = allowed;
+    }
+
+    /// Lowest and highest SP of the stack area. An instruction that leaves SP
+    /// outside it fails with a stack overflow or underflow. Unchecked by default.
+    pub fn set_stack_limits(&mut self, stack: Option<RangeInclusive<u16>>) {
+        self.stack = stack;
+    }
+
+    /// Executes one instruction and returns the number of cycles it took. A pending
+    /// interrupt is acknowledged first if interrupts are enabled, executing the
+    /// instruction supplied with it. Fails when the CPU is halted with interrupts
+    /// disabled, and after an instruction the machine could not carry out.
+    pub fn step(&mut self) -> Result<u32, EmulationError> {
+        if self.state.halted && !self.state.int_enable {
+            // PC is past the HLT
+            let pc = self.state.pc.wrapping_sub(1);
+            return Err(EmulationError::HaltDeadlock { pc });
+        }
+        let idle = self.state.halted && !self.acknowledges_interrupt();
+        let bytes = self.next_instruction();
+        let (pc, opcode, sp) = (self.state.pc, bytes[0], self.state.sp);
+        if !idle && !self.undocumented && opcodes::undocumented(opcode) {
+            return Err(EmulationError::IllegalOpcode { pc, opcode });
+        }
+
+        let mut cycles: isize = 0;
+        let acknowledge = self.state.int_enable && !self.state.int_delay;
+        self.state.int_delay = false;
+        match self.state.interrupt_request {
+            Some(instruction) if acknowledge => {
+                self.state.interrupt_request = None;
+                self.state.int_enable = false;
+                self.state.halted = false;
+                let mut memory = Injected {
+                    memory: &mut self.memory,
+                    pc: self.state.pc,
+                    instruction,
+                };
+                emulate_instruction(&mut self.state, &mut memory, &mut self.io, &mut cycles, true)
+            }
+            _ => emulate_instruction(&mut self.state, &mut self.memory, &mut self.io, &mut cycles, false),
+        }
+        let cycles = cycles as u32;
+        self.memory.tick(cycles);
+
+        if let Some(addr) = self.memory.take_fault() {
+            return Err(EmulationError::MemoryRange { pc, opcode, addr, cycles });
+        }
+        if let Some(message) = self.io.take_fault() {
+            let port = bytes[1];
+            return Err(EmulationError::Io { pc, opcode, port, message, cycles });
+        }
+        if let Some(stack) = self.stack.as_ref().filter(|_| self.state.sp != sp) {
+            let sp = self.state.sp;
+            if sp < *stack.start() {
+                return Err(EmulationError::StackOverflow { pc, opcode, sp, cycles });
+            }
+            if sp > *stack.end() {
+                return Err(EmulationError::StackUnderflow { pc, opcode, sp, cycles });
+            }
+        }
+        Ok(cycles)
+    }
+
+    /// Raises the interrupt line with `RST rst` on the data bus.
+    pub fn interrupt(&mut self, rst: u8) {
+        self.request_interrupt([0xc7 | ((rst & 0x7) << 3), 0, 0]);
+    }
+
+    /// Raises the interrupt line with an arbitrary instruction on the data bus. It is
+    /// executed without advancing PC, so a CALL or RST pushes the address of the
+    /// interrupted instruction. Replaces any request that is still pending.
+    pub fn request_interrupt(&mut self, instruction: [u8; 3]) {
+        self.state.interrupt_request = Some(instruction);
+    }
+
+    pub fn interrupt_pending(&self) -> bool {
+        self.state.interrupt_request.is_some()
+    }
+
+    /// Whether the next `step` acknowledges the pending interrupt.
+    pub fn acknowledges_interrupt(&self) -> bool {
+        self.state.int_enable && !self.state.int_delay && self.interrupt_pending()
+    }
+
+    /// The instruction the next `step` executes, taken from the data bus during an
+    /// interrupt acknowledge and from memory at PC otherwise.
+    pub fn next_instruction(&self) -> [u8; 3] {
+        match self.state.interrupt_request {
+            Some(instruction) if self.acknowledges_interrupt() => instruction,
+            _ => {
+                let pc = self.state.pc;
+                [
+                    self.memory.peek(pc),
+                    self.memory.peek(pc.wrapping_add(1)),
+                    self.memory.peek(pc.wrapping_add(2)),
+                ]
+            }
+        }
+    }
+
+    pub fn state(&self) -> &State8080 {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut State8080 {
+        &mut self.state
+    }
+
+    pub fn io(&self) -> &I {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut I {
+        &mut self.io
+    }
+
+    pub fn memory(&self) -> &M {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut M {
+        &mut self.memory
+    }
+
+    pub fn flags(&self) -> &ConditionCodes {
+        &self.state.cc
+    }
+
+    pub fn a(&self) -> u8 {
+        self.state.a
+    }
+
+    pub fn b(&self) -> u8 {
+        self.state.b
+    }
+
+    pub fn c(&self) -> u8 {
+        self.state.c
+    }
+
+    pub fn d(&self) -> u8 {
+        self.state.d
+    }
+
+    pub fn e(&self) -> u8 {
+        self.state.e
+    }
+
+    pub fn h(&self) -> u8 {
+        self.state.h
+    }
+
+    pub fn l(&self) -> u8 {
+        self.state.l
+    }
+
+    pub fn sp(&self) -> u16 {
+        self.state.sp
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.state.pc
+    }
+
+    pub fn bc(&self) -> u16 {
+        u16::from_be_bytes([self.state.b, self.state.c])
+    }
+
+    pub fn de(&self) -> u16 {
+        u16::from_be_bytes([self.state.d, self.state.e])
+    }
+
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.state.h, self.state.l])
+    }
+
+    /// Accumulator and flags as pushed by PUSH PSW.
+    pub fn psw(&self) -> u16 {
+        u16::from_be_bytes([self.state.a, self.state.cc.psw()])
+    }
+
+    pub fn set_sp(&mut self, sp: u16) {
+        self.state.sp = sp;
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.state.pc = pc;
+    }
+
+    pub fn set_bc(&mut self, bc: u16) {
+        [self.state.b, self.state.c] = bc.to_be_bytes();
+    }
+
+    pub fn set_de(&mut self, de: u16) {
+        [self.state.d, self.state.e] = de.to_be_bytes();
+    }
+
+    pub fn set_hl(&mut self, hl: u16) {
+        [self.state.h, self.state.l] = hl.to_be_bytes();
+    }
+
+    pub fn set_psw(&mut self, psw: u16) {
+        let [a, flags] = psw.to_be_bytes();
+        self.state.a = a;
+        self.state.cc.set_psw(flags);
+    }
+
+    pub fn int_enabled(&self) -> bool {
+        self.state.int_enable
+    }
+
+    pub fn halted(&self) -> bool {
+        self.state.halted
+    }
+}
+
+/// Serves the bytes of an interrupt instruction in place of the memory at PC.
+struct Injected<'a, M: Bus> {
+    memory: &'a mut M,
+    pc: u16,
+    instruction: [u8; 3],
+}
+
+impl<M: Bus> Bus for Injected<'_, M> {
+    fn peek(&self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.pc) {
+            offset @ 0..=2 => self.instruction[usize::from(offset)],
+            _ => self.memory.peek(addr),
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr.wrapping_sub(self.pc) {
+            offset @ 0..=2 => self.instruction[usize::from(offset)],
+            _ => self.memory.read(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.memory.write(addr, value);
+    }
+}
+
+fn emulate_instruction<M: Bus, I: IoBus>(
+    state: &mut State8080,
+    memory: &mut M,
+    io: &mut I,
+    cycles: &mut isize,
+    injected: bool,
+) {
+    // HLT: idle until an interrupt is acknowledged, the clock keeps running
+    if state.halted && !injected {
+        *cycles += 4;
+        return;
+    }
+
+    let opcode: u8 = memory.read(state.pc);
+    *cycles += isize::from(opcodes::CYCLES[usize::from(opcode)]);
+    let pc: u16 = state.pc;
+
+    // an injected instruction does not come from memory, so PC stays put
+    if !injected {
+        state.pc = state.pc.wrapping_add(u16::from(opcodes::SIZE[usize::from(opcode)]));
+    }
+
+    match opcode {
+        // NOP
+        0x00 => instructions::nop(),
+
+        // LXI B,word
+        0x01 => {
+            instructions::lxi(&mut state.b, &mut state.c, &pc, memory);
+        }
+
+        // STAX B
+        0x02 => {
+            instructions::stax(&state.a, &state.b, &state.c, memory);
+        }
+
+        // INX B
+        0x03 => {
+            instructions::inx(&mut state.b, &mut state.c);
+        }
+
+        // INR B
+        0x04 => instructions::inr(&mut state.b, &mut state.cc),
+
+        // DCR B
+        0x05 => instructions::dcr(&mut state.b, &mut state.cc),
+
+        // MVI B,D8
+        0x06 => {
+            instructions::mvi(&mut state.b, memory, &pc);
+        }
+
+        // RLC
+        0x07 => instructions::rlc(&mut state.a, &mut state.cc),
+
+        // NOP (undocumented)
+        0x08 => instructions::nop(),
+
+        // DAD B
+        0x09 => {
+            instructions::dad(
+                &mut state.h,
+                &mut state.l,
+                &state.b,
+                &state.c,
+                &mut state.cc,
+            );
+        }
+
+        // LDAX B
+        0x0a => {
+            instructions::ldax(&mut state.a, &state.b, &state.c, memory);
+        }
+
+        // DCX B
+        0x0b => {
+            instructions::dcx(&mut state.b, &mut state.c);
+        }
+
+        // INR C
+        0x0c => instructions::inr(&mut state.c, &mut state.cc),
+
+        // DCR C
+        0x0d => instructions::dcr(&mut state.c, &mut state.cc),
+
+        // MVI C,D8
+        0x0e => {
+            instructions::mvi(&mut state.c, memory, &pc);
+        }
+
+        // RRC
+        0x0f => instructions::rrc(&mut state.a, &mut state.cc),
+
+        // NOP (undocumented)
+        0x10 => instructions::nop(),
+
+        // LXI D,word
+        0x11 => {
+            instructions::lxi(&mut state.d, &mut state.e, &pc, memory);
+        }
+
+        // STAX D
+        0x12 => {
+            instructions::stax(&state.a, &state.d, &state.e, memory);
+        }
+
+        // INX D
+        0x13 => {
+            instructions::inx(&mut state.d, &mut state.e);
+        }
+
+        // INR D
+        0x14 => instructions::inr(&mut state.d, &mut state.cc),
+
+        // DCR D
+        0x15 => instructions::dcr(&mut state.d, &mut state.cc),
+
+        // MVI D,D8
+        0x16 => {
+            instructions::mvi(&mut state.d, memory, &pc);
+        }
+
+        // RAL
+        0x17 => instructions::ral(&mut state.a, &mut state.cc),
+
+        // NOP (undocumented)
+        0x18 => instructions::nop(),
+
+        // DAD D
+        0x19 => {
+            instructions::dad(
+                &mut state.h,
+                &mut state.l,
+                &state.d,
+                &state.e,
+                &mut state.cc,
+            );
+        }
+
+        // LDAX D
+        0x1a => {
+            instructions::ldax(&mut state.a, &state.d, &state.e, memory);
+        }
+
+        // DCX D
+        0x1b => {
+            instructions::dcx(&mut state.d, &mut state.e);
+        }
+
+        // INR E
+        0x1c => instructions::inr(&mut state.e, &mut state.cc),
+
+        // DCR E
+        0x1d => instructions::dcr(&mut state.e, &mut state.cc),
+
+        // MVI E,D8
+        0x1e => {
+            instructions::mvi(&mut state.e, memory, &pc);
+        }
+
+        // RAR
+        0x1f => instructions::rar(&mut state.a, &mut state.cc),
+
+        // NOP (undocumented)
+        0x20 => instructions::nop(),
+
+        // LXI H,word
+        0x21 => {
+            instructions::lxi(&mut state.h, &mut state.l, &pc, memory);
+        }
+
+        // SHLD adr
+        0x22 => {
+            let offset = instructions::read_word(memory, pc.wrapping_add(1));
+            memory.write(offset, state.l);
+            memory.write(offset.wrapping_add(1), state.h);
+        }
+
+        // INX H
+        0x23 => {
+            instructions::inx(&mut state.h, &mut state.l);
+        }
+
+        // INR H
+        0x24 => instructions::inr(&mut state.h, &mut state.cc),
+
+        // DCR H
+        0x25 => instructions::dcr(&mut state.h, &mut state.cc),
+
+        // MVI H,D8
+        0x26 => {
+            instructions::mvi(&mut state.h, memory, &pc);
+        }
+
+        // DAA
+        0x27 => instructions::daa(&mut state.a, &mut state.cc),
+
+        // NOP (undocumented)
+        0x28 => instructions::nop(),
+
+        // DAD H
+        0x29 => {
+            let h = state.h;
+            let l = state.l;
+            instructions::dad(&mut state.h, &mut state.l, &h, &l, &mut state.cc);
+        }
+
+        // LHLD adr
+        0x2a => {
+            let offset = instructions::read_word(memory, pc.wrapping_add(1));
+            state.l = memory.read(offset);
+            state.h = memory.read(offset.wrapping_add(1));
+        }
+
+        // DCX H
+        0x2b => {
+            instructions::dcx(&mut state.h, &mut state.l);
+        }
+
+        // INR L
+        0x2c => instructions::inr(&mut state.l, &mut state.cc),
+
+        // DCR L
+        0x2d => instructions::dcr(&mut state.l, &mut state.cc),
+
+        // MVI L,D8
+        0x2e => {
+            instructions::mvi(&mut state.l, memory, &pc);
+        }
+
+        // CMA (not)
+        0x2f => state.a = !state.a,
+
+        // NOP (undocumented)
+        0x30 => instructions::nop(),
+
+        // LXI SP,word
+        0x31 => {
+            state.sp = instructions::read_word(memory, pc.wrapping_add(1));
+        }
+
+        // STA adr
+        0x32 => {
+            let offset = instructions::read_word(memory, pc.wrapping_add(1));
+            memory.write(offset, state.a);
+        }
+
+        // INX SP
+        0x33 => {
+            state.sp = state.sp.wrapping_add(1);
+        }
+
+        // INR M
+        0x34 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let mut m = memory.read(offset);
+            instructions::inr(&mut m, &mut state.cc);
+            memory.write(offset, m);
+        }
+
+        // DCR M
+        0x35 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let mut m = memory.read(offset);
+            instructions::dcr(&mut m, &mut state.cc);
+            memory.write(offset, m);
+        }
+
+        // MVI M,D8
+        0x36 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let d8 = memory.read(pc.wrapping_add(1));
+            memory.write(offset, d8);
+        }
+
+        // STC
+        0x37 => state.cc.cy = true,
+
+        // NOP (undocumented)
+        0x38 => instructions::nop(),
+
+        // DAD SP
+        0x39 => {
+            let sp = state.sp.to_be_bytes();
+            instructions::dad(&mut state.h, &mut state.l, &sp[0], &sp[1], &mut state.cc);
+        }
+
+        // LDA adr
+        0x3a => {
+            let offset = instructions::read_word(memory, pc.wrapping_add(1));
+            state.a = memory.read(offset);
+        }
+
+        // DCX SP
+        0x3b => {
+            state.sp = state.sp.wrapping_sub(1);
+        }
+
+        // INR A
+        0x3c => instructions::inr(&mut state.a, &mut state.cc),
+
+        // DCR A
+        0x3d => instructions::dcr(&mut state.a, &mut state.cc),
+
+        // MVI A,D8
+        0x3e => {
+            instructions::mvi(&mut state.a, memory, &pc);
+        }
+
+        // CMC
+        0x3f => state.cc.cy = !state.cc.cy,
+
+        // MOV B,B
+        0x40 => (),
+
+        // MOV B,C
+        0x41 => state.b = state.c,
+
+        // MOV B,D
+        0x42 => state.b = state.d,
+
+        // MOV B,E
+        0x43 => state.b = state.e,
+
+        // MOV B,H
+        0x44 => state.b = state.h,
+
+        // MOV B,L
+        0x45 => state.b = state.l,
+
+        // MOV B,M
+        0x46 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.b = memory.read(offset);
+        }
+
+        // MOV B,A
+        0x47 => state.b = state.a,
+
+        // MOV C,B
+        0x48 => state.c = state.b,
+
+        // MOV C,C
+        0x49 => (),
+
+        // MOV C,D
+        0x4a => state.c = state.d,
+
+        // MOV C,E
+        0x4b => state.c = state.e,
+
+        // MOV C,H
+        0x4c => state.c = state.h,
+
+        // MOV C,L
+        0x4d => state.c = state.l,
+
+        // MOV C,M
+        0x4e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.c = memory.read(offset);
+        }
+
+        // MOV C,A
+        0x4f => state.c = state.a,
+
+        // MOV D,B
+        0x50 => state.d = state.b,
+
+        // MOV D,C
+        0x51 => state.d = state.c,
+
+        // MOV D,D
+        0x52 => (),
+
+        // MOV D,E
+        0x53 => state.d = state.e,
+
+        // MOV D,H
+        0x54 => state.d = state.h,
+
+        // MOV D,L
+        0x55 => state.d = state.l,
+
+        // MOV D,M
+        0x56 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.d = memory.read(offset);
+        }
+
+        // MOV D,A
+        0x57 => state.d = state.a,
+
+        // MOV E,B
+        0x58 => state.e = state.b,
+
+        // MOV E,C
+        0x59 => state.e = state.c,
+
+        // MOV E,D
+        0x5a => state.e = state.d,
+
+        // MOV E,E
+        0x5b => (),
+
+        // MOV E,H
+        0x5c => state.e = state.h,
+
+        // MOV E,L
+        0x5d => state.e = state.l,
+
+        // MOV E,M
+        0x5e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.e = memory.read(offset);
+        }
+
+        // MOV E,A
+        0x5f => state.e = state.a,
+
+        // MOV H,B
+        0x60 => state.h = state.b,
+
+        // MOV H,C
+        0x61 => state.h = state.c,
+
+        // MOV H,D
+        0x62 => state.h = state.d,
+
+        // MOV H,E
+        0x63 => state.h = state.e,
+
+        // MOV H,H
+        0x64 => (),
+
+        // MOV H,L
+        0x65 => state.h = state.l,
+
+        // MOV H,M
+        0x66 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.h = memory.read(offset);
+        }
+
+        // MOV H,A
+        0x67 => state.h = state.a,
+
+        // MOV L,B
+        0x68 => state.l = state.b,
+
+        // MOV L,C
+        0x69 => state.l = state.c,
+
+        // MOV L,D
+        0x6a => state.l = state.d,
+
+        // MOV L,E
+        0x6b => state.l = state.e,
+
+        // MOV L,H
+        0x6c => state.l = state.h,
+
+        // MOV L,L
+        0x6d => (),
+
+        // MOV L,M
+        0x6e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.l = memory.read(offset);
+        }
+
+        // MOV L,A
+        0x6f => state.l = state.a,
+
+        // MOV M,B
+        0x70 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.b);
+        }
+
+        // MOV M,C
+        0x71 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.c);
+        }
+
+        // MOV M,D
+        0x72 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.d);
+        }
+
+        // MOV M,E
+        0x73 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.e);
+        }
+
+        // MOV M,H
+        0x74 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.h);
+        }
+
+        // MOV M,L
+        0x75 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.l);
+        }
+
+        // HLT
+        0x76 => {
+            state.halted = true;
+        }
+
+        // MOV M,A
+        0x77 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            memory.write(offset, state.a);
+        }
+
+        // MOV A,B
+        0x78 => state.a = state.b,
+
+        // MOV A,C
+        0x79 => state.a = state.c,
+
+        // MOV A,D
+        0x7a => state.a = state.d,
+
+        // MOV A,E
+        0x7b => state.a = state.e,
+
+        // MOV A,H
+        0x7c => state.a = state.h,
+
+        // MOV A,L
+        0x7d => state.a = state.l,
+
+        // MOV A,M
+        0x7e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            state.a = memory.read(offset);
+        }
+
+        // MOV A,A
+        0x7f => (),
+
+        // ADD B
+        0x80 => instructions::add(&mut state.a, &state.b, &mut state.cc),
+
+        // ADD C
+        0x81 => instructions::add(&mut state.a, &state.c, &mut state.cc),
+
+        // ADD D
+        0x82 => instructions::add(&mut state.a, &state.d, &mut state.cc),
+
+        // ADD E
+        0x83 => instructions::add(&mut state.a, &state.e, &mut state.cc),
+
+        // ADD H
+        0x84 => instructions::add(&mut state.a, &state.h, &mut state.cc),
+
+        // ADD L
+        0x85 => instructions::add(&mut state.a, &state.l, &mut state.cc),
+
+        // ADD M
+        0x86 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::add(&mut state.a, &m, &mut state.cc);
+        }
+
+        // ADD A
+        0x87 => {
+            let a = state.a;
+            instructions::add(&mut state.a, &a, &mut state.cc);
+        }
+
+        // ADC B
+        0x88 => instructions::adc(&mut state.a, &state.b, &mut state.cc),
+
+        // ADC C
+        0x89 => instructions::adc(&mut state.a, &state.c, &mut state.cc),
+
+        // ADC D
+        0x8a => instructions::adc(&mut state.a, &state.d, &mut state.cc),
+
+        // ADC E
+        0x8b => instructions::adc(&mut state.a, &state.e, &mut state.cc),
+
+        // ADC H
+        0x8c => instructions::adc(&mut state.a, &state.h, &mut state.cc),
+
+        // ADC L
+        0x8d => instructions::adc(&mut state.a, &state.l, &mut state.cc),
+
+        // ADC M
+        0x8e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::adc(&mut state.a, &m, &mut state.cc);
+        }
+
+        // ADC A
+        0x8f => {
+            let a = state.a;
+            instructions::adc(&mut state.a, &a, &mut state.cc);
+        }
+
+        // SUB B
+        0x90 => instructions::sub(&mut state.a, &state.b, &mut state.cc),
+
+        // SUB C
+        0x91 => instructions::sub(&mut state.a, &state.c, &mut state.cc),
+
+        // SUB D
+        0x92 => instructions::sub(&mut state.a, &state.d, &mut state.cc),
+
+        // SUB E
+        0x93 => instructions::sub(&mut state.a, &state.e, &mut state.cc),
+
+        // SUB H
+        0x94 => instructions::sub(&mut state.a, &state.h, &mut state.cc),
+
+        // SUB L
+        0x95 => instructions::sub(&mut state.a, &state.l, &mut state.cc),
+
+        // SUB M
+        0x96 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::sub(&mut state.a, &m, &mut state.cc);
+        }
+
+        // SUB A
+        0x97 => {
+            let a = state.a;
+            instructions::sub(&mut state.a, &a, &mut state.cc);
+        }
+
+        // SBB B
+        0x98 => instructions::sbb(&mut state.a, &state.b, &mut state.cc),
+
+        // SBB C
+        0x99 => instructions::sbb(&mut state.a, &state.c, &mut state.cc),
+
+        // SBB D
+        0x9a => instructions::sbb(&mut state.a, &state.d, &mut state.cc),
+
+        // SBB E
+        0x9b => instructions::sbb(&mut state.a, &state.e, &mut state.cc),
+
+        // SBB H
+        0x9c => instructions::sbb(&mut state.a, &state.h, &mut state.cc),
+
+        // SBB L
+        0x9d => instructions::sbb(&mut state.a, &state.l, &mut state.cc),
+
+        // SBB M
+        0x9e => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::sbb(&mut state.a, &m, &mut state.cc);
+        }
+
+        // SBB A
+        0x9f => {
+            let a = state.a;
+            instructions::sbb(&mut state.a, &a, &mut state.cc);
+        }
+
+        // ANA B
+        0xa0 => instructions::ana(&mut state.a, &state.b, &mut state.cc),
+
+        // ANA C
+        0xa1 => instructions::ana(&mut state.a, &state.c, &mut state.cc),
+
+        // ANA D
+        0xa2 => instructions::ana(&mut state.a, &state.d, &mut state.cc),
+
+        // ANA E
+        0xa3 => instructions::ana(&mut state.a, &state.e, &mut state.cc),
+
+        // ANA H
+        0xa4 => instructions::ana(&mut state.a, &state.h, &mut state.cc),
+
+        // ANA L
+        0xa5 => instructions::ana(&mut state.a, &state.l, &mut state.cc),
+
+        // ANA M
+        0xa6 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::ana(&mut state.a, &m, &mut state.cc);
+        }
+
+        // ANA A
+        0xa7 => {
+            let a = state.a;
+            instructions::ana(&mut state.a, &a, &mut state.cc);
+        }
+
+        // XRA B
+        0xa8 => instructions::xra(&mut state.a, &state.b, &mut state.cc),
+
+        // XRA C
+        0xa9 => instructions::xra(&mut state.a, &state.c, &mut state.cc),
+
+        // XRA D
+        0xaa => instructions::xra(&mut state.a, &state.d, &mut state.cc),
+
+        // XRA E
+        0xab => instructions::xra(&mut state.a, &state.e, &mut state.cc),
+
+        // XRA H
+        0xac => instructions::xra(&mut state.a, &state.h, &mut state.cc),
+
+        // XRA L
+        0xad => instructions::xra(&mut state.a, &state.l, &mut state.cc),
+
+        // XRA M
+        0xae => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::xra(&mut state.a, &m, &mut state.cc);
+        }
+
+        // XRA A
+        0xaf => {
+            let a = state.a;
+            instructions::xra(&mut state.a, &a, &mut state.cc);
+        }
+
+        // ORA B
+        0xb0 => instructions::ora(&mut state.a, &state.b, &mut state.cc),
+
+        // ORA C
+        0xb1 => instructions::ora(&mut state.a, &state.c, &mut state.cc),
+
+        // ORA D
+        0xb2 => instructions::ora(&mut state.a, &state.d, &mut state.cc),
+
+        // ORA E
+        0xb3 => instructions::ora(&mut state.a, &state.e, &mut state.cc),
+
+        // ORA H
+        0xb4 => instructions::ora(&mut state.a, &state.h, &mut state.cc),
+
+        // ORA L
+        0xb5 => instructions::ora(&mut state.a, &state.l, &mut state.cc),
+
+        // ORA M
+        0xb6 => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::ora(&mut state.a, &m, &mut state.cc);
+        }
+
+        // ORA A
+        0xb7 => {
+            let a = state.a;
+            instructions::ora(&mut state.a, &a, &mut state.cc);
+        }
+
+        // CMP B
+        0xb8 => instructions::cmp(&mut state.a, &state.b, &mut state.cc),
+
+        // CMP C
+        0xb9 => instructions::cmp(&mut state.a, &state.c, &mut state.cc),
+
+        // CMP D
+        0xba => instructions::cmp(&mut state.a, &state.d, &mut state.cc),
+
+        // CMP E
+        0xbb => instructions::cmp(&mut state.a, &state.e, &mut state.cc),
+
+        // CMP H
+        0xbc => instructions::cmp(&mut state.a, &state.h, &mut state.cc),
+
+        // CMP L
+        0xbd => instructions::cmp(&mut state.a, &state.l, &mut state.cc),
+
+        // CMP M
+        0xbe => {
+            let offset = (u16::from(state.h) << 8) | u16::from(state.l);
+            let m = memory.read(offset);
+            instructions::cmp(&mut state.a, &m, &mut state.cc);
+        }
+
+        // CMP A
+        0xbf => {
+            let a = state.a;
+            instructions::cmp(&mut state.a, &a, &mut state.cc);
+        }
+
+        // RNZ
+        0xc0 => {
+            if !state.cc.z {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // POP B
+        0xc1 => {
+            instructions::pop(&mut state.b, &mut state.c, &mut state.sp, memory);
+        }
+
+        // JNZ adr
+        0xc2 => {
+            if !state.cc.z {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // JMP adr
+        0xc3 => {
+            instructions::jmp(&mut state.pc, memory, &pc);
+        }
+
+        // CNZ adr
+        0xc4 => {
+            if !state.cc.z {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // PUSH B
+        0xc5 => {
+            instructions::push(&state.b, &state.c, &mut state.sp, memory);
+        }
+
+        // ADI D8
+        0xc6 => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::add(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 0
+        0xc7 => {
+            instructions::rst(0, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RZ
+        0xc8 => {
+            if state.cc.z {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // RET
+        0xc9 => {
+            instructions::ret(&mut state.pc, &mut state.sp, memory);
+        }
+
+        // JZ adr
+        0xca => {
+            if state.cc.z {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // JMP adr (undocumented)
+        0xcb => {
+            instructions::jmp(&mut state.pc, memory, &pc);
+        }
+
+        // CZ adr
+        0xcc => {
+            if state.cc.z {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // CALL adr
+        0xcd => {
+            instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+        }
+
+        // ACI D8
+        0xce => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::adc(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 1
+        0xcf => {
+            instructions::rst(1, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RNC
+        0xd0 => {
+            if !state.cc.cy {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // POP D
+        0xd1 => {
+            instructions::pop(&mut state.d, &mut state.e, &mut state.sp, memory);
+        }
+
+        // JNC adr
+        0xd2 => {
+            if !state.cc.cy {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // OUT D8
+        0xd3 => {
+            let port = memory.read(pc.wrapping_add(1));
+            io.output(port, state.a);
+        }
+
+        // CNC adr
+        0xd4 => {
+            if !state.cc.cy {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // PUSH D
+        0xd5 => {
+            instructions::push(&state.d, &state.e, &mut state.sp, memory);
+        }
+
+        // SUI D8
+        0xd6 => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::sub(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 2
+        0xd7 => {
+            instructions::rst(2, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RC
+        0xd8 => {
+            if state.cc.cy {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // RET (undocumented)
+        0xd9 => {
+            instructions::ret(&mut state.pc, &mut state.sp, memory);
+        }
+
+        // JC adr
+        0xda => {
+            if state.cc.cy {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // IN D8
+        0xdb => {
+            let port = memory.read(pc.wrapping_add(1));
+            state.a = io.input(port);
+        }
+
+        // CC adr
+        0xdc => {
+            if state.cc.cy {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // CALL adr (undocumented)
+        0xdd => {
+            instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+        }
+
+        // SBI D8
+        0xde => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::sbb(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 3
+        0xdf => {
+            instructions::rst(3, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RPO
+        0xe0 => {
+            if !state.cc.p {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // POP H
+        0xe1 => {
+            instructions::pop(&mut state.h, &mut state.l, &mut state.sp, memory);
+        }
+
+        // JPO adr
+        0xe2 => {
+            if !state.cc.p {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // XTHL
+        0xe3 => {
+            let sp = state.sp;
+            let bufferh = state.h;
+            let bufferl = state.l;
+            state.h = memory.read(sp.wrapping_add(1));
+            state.l = memory.read(sp);
+            memory.write(sp.wrapping_add(1), bufferh);
+            memory.write(sp, bufferl);
+        }
+
+        // CPO adr
+        0xe4 => {
+            if !state.cc.p {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // PUSH H
+        0xe5 => {
+            instructions::push(&state.h, &state.l, &mut state.sp, memory);
+        }
+
+        // ANI D8
+        0xe6 => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::ana(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 4
+        0xe7 => {
+            instructions::rst(4, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RPE
+        0xe8 => {
+            if state.cc.p {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // PCHL
+        0xe9 => {
+            state.pc = (u16::from(state.h) << 8) | u16::from(state.l);
+        }
+
+        // JPE adr
+        0xea => {
+            if state.cc.p {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // XCHG
+        0xeb => {
+            std::mem::swap(&mut state.d, &mut state.h);
+            std::mem::swap(&mut state.e, &mut state.l);
+        }
+
+        // CPE adr
+        0xec => {
+            if state.cc.p {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // CALL adr (undocumented)
+        0xed => {
+            instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+        }
+
+        // XRI D8
+        0xee => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::xra(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 5
+        0xef => {
+            instructions::rst(5, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RP
+        0xf0 => {
+            if !state.cc.s {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // POP PSW
+        0xf1 => {
+            let mut psw: u8 = 0;
+            instructions::pop(&mut state.a, &mut psw, &mut state.sp, memory);
+            state.cc.set_psw(psw);
+        }
+
+        // JP adr
+        0xf2 => {
+            if !state.cc.s {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // DI
+        0xf3 => {
+            state.int_enable = false;
+        }
+
+        // CP adr
+        0xf4 => {
+            if !state.cc.s {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // PUSH PSW
+        0xf5 => {
+            let psw = state.cc.psw();
+            instructions::push(&state.a, &psw, &mut state.sp, memory);
+        }
+
+        // ORI D8
+        0xf6 => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::ora(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 6
+        0xf7 => {
+            instructions::rst(6, &mut state.pc, &mut state.sp, memory);
+        }
+
+        // RM
+        0xf8 => {
+            if state.cc.s {
+                instructions::ret(&mut state.pc, &mut state.sp, memory);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // SPHL
+        0xf9 => {
+            state.sp = (u16::from(state.h) << 8) | u16::from(state.l);
+        }
+
+        // JM adr
+        0xfa => {
+            if state.cc.s {
+                instructions::jmp(&mut state.pc, memory, &pc);
+            }
+        }
+
+        // EI
+        0xfb => {
+            state.int_enable = true;
+            state.int_delay = true;
+        }
+
+        // CM adr
+        0xfc => {
+            if state.cc.s {
+                instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+            } else {
+                *cycles -= 6;
+            }
+        }
+
+        // CALL adr (undocumented)
+        0xfd => {
+            instructions::call(&mut state.pc, &mut state.sp, memory, &pc);
+        }
+
+        // CPI D8
+        0xfe => {
+            let d8 = memory.read(pc.wrapping_add(1));
+            instructions::cmp(&mut state.a, &d8, &mut state.cc);
+        }
+
+        // RST 7
+        0xff => {
+            instructions::rst(7, &mut state.pc, &mut state.sp, memory);
+        }
+    }
+}
+
+/// Registers, memory and devices.
+impl<M: Bus + Snapshot, I: IoBus + Snapshot> Snapshot for Cpu8080<M, I> {
+    fn save(&self, out: &mut Vec<u8>) {
+        self.state.save(out);
+        self.memory.save(out);
+        self.io.save(out);
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        self.state.restore(input)?;
+        self.memory.restore(input)?;
+        self.io.restore(input)
+    }
+}
@@ -1,8 +1,146 @@
-#[derive(Debug)]
+use crate::cpu::Cpu8080;
+use crate::disasm;
+use crate::io::IoBus;
+use crate::memory::Bus;
+use std::fmt;
+
+/// CPU state just before an instruction executes, one entry of a trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Instructioninfo {
-    pub instr_n: usize,
+    /// instructions executed before this one
+    pub instr_n: u64,
+    /// T-states elapsed before this instruction
+    pub cycles: u64,
     pub opcode: u8,
+    pub operands: [u8; 2],
     pub pc: u16,
     pub sp: u16,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    /// flag byte as pushed by PUSH PSW
+    pub flags: u8,
     pub int_enable: bool,
-}
\ No newline at end of file
+    pub halted: bool,
+    /// the instruction came from the data bus during an interrupt acknowledge
+    pub interrupt: bool,
+}
+
+impl Instructioninfo {
+    /// Size of one record in a binary trace.
+    pub const ENCODED_LEN: usize = 32;
+
+    pub fn capture<M: Bus, I: IoBus>(cpu: &Cpu8080<M, I>, instr_n: u64, cycles: u64) -> Self {
+        let state = cpu.state();
+        let [opcode, operand1, operand2] = cpu.next_instruction();
+        Instructioninfo {
+            instr_n,
+            cycles,
+            opcode,
+            operands: [operand1, operand2],
+            pc: state.pc,
+            sp: state.sp,
+            a: state.a,
+            b: state.b,
+            c: state.c,
+            d: state.d,
+            e: state.e,
+            h: state.h,
+            l: state.l,
+            flags: state.cc.psw(),
+            int_enable: state.int_enable,
+            halted: state.halted,
+            interrupt: cpu.acknowledges_interrupt(),
+        }
+    }
+
+    /// The instruction in Intel mnemonics.
+    pub fn mnemonic(&self) -> String {
+        let bytes = [self.opcode, self.operands[0], self.operands[1]];
+        disasm::decode(&bytes).expect("three bytes hold any instruction")
+    }
+
+    /// Little endian record: instruction number, cycles, PC, SP, A, flags, B-L,
+    /// the three instruction bytes and a byte of status bits.
+    pub fn encode(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut record = [0; Self::ENCODED_LEN];
+        record[0..8].copy_from_slice(&self.instr_n.to_le_bytes());
+        record[8..16].copy_from_slice(&self.cycles.to_le_bytes());
+        record[16..18].copy_from_slice(&self.pc.to_le_bytes());
+        record[18..20].copy_from_slice(&self.sp.to_le_bytes());
+        record[20..28].copy_from_slice(&[self.a, self.flags, self.b, self.c, self.d, self.e, self.h, self.l]);
+        record[28..31].copy_from_slice(&[self.opcode, self.operands[0], self.operands[1]]);
+        record[31] = u8::from(self.int_enable) | u8::from(self.halted) << 1 | u8::from(self.interrupt) << 2;
+        record
+    }
+
+    pub fn decode(record: &[u8; Self::ENCODED_LEN]) -> Self {
+        let u64_at = |i: usize| u64::from_le_bytes(record[i..i + 8].try_into().unwrap());
+        let u16_at = |i: usize| u16::from_le_bytes([record[i], record[i + 1]]);
+        Instructioninfo {
+            instr_n: u64_at(0),
+            cycles: u64_at(8),
+            pc: u16_at(16),
+            sp: u16_at(18),
+            a: record[20],
+            flags: record[21],
+            b: record[22],
+            c: record[23],
+            d: record[24],
+            e: record[25],
+            h: record[26],
+            l: record[27],
+            opcode: record[28],
+            operands: [record[29], record[30]],
+            int_enable: record[31] & 0x1 != 0,
+            halted: record[31] & 0x2 != 0,
+            interrupt: record[31] & 0x4 != 0,
+        }
+    }
+}
+
+impl fmt::Display for Instructioninfo {
+    /// One line of a text trace.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flag = |bit: u8, name: char| if self.flags & bit != 0 { name } else { '-' };
+        let size = disasm::size(self.opcode);
+        let bytes = [self.opcode, self.operands[0], self.operands[1]];
+        let bytes: Vec<String> = bytes[..size].iter().map(|b| format!("{:02x}", b)).collect();
+        write!(
+            f,
+            "{:>10} {:>12}  {:04x}  {:<8}  {:<14} A={:02x} BC={:02x}{:02x} DE={:02x}{:02x} HL={:02x}{:02x} SP={:04x} F={}{}{}{}{}",
+            self.instr_n,
+            self.cycles,
+            self.pc,
+            bytes.join(" "),
+            self.mnemonic(),
+            self.a,
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp,
+            flag(0x80, 'S'),
+            flag(0x40, 'Z'),
+            flag(0x10, 'A'),
+            flag(0x04, 'P'),
+            flag(0x01, 'C'),
+        )?;
+        if self.int_enable {
+            write!(f, " EI")?;
+        }
+        if self.halted {
+            write!(f, " HALT")?;
+        }
+        if self.interrupt {
+            write!(f, " INT")?;
+        }
+        Ok(())
+    }
+}
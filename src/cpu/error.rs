@@ -0,0 +1,92 @@
+use crate::disasm;
+use crate::rom::RomError;
+use std::fmt;
+
+/// Why emulation cannot go on. Errors raised by an instruction carry its address
+/// and opcode, and the cycles it took when it had already run.
+#[derive(Debug)]
+pub enum EmulationError {
+    /// An undocumented opcode while they are not allowed.
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// The memory bus refused an access to `addr`.
+    MemoryRange { pc: u16, opcode: u8, addr: u16, cycles: u32 },
+    /// SP went below the stack area.
+    StackOverflow { pc: u16, opcode: u8, sp: u16, cycles: u32 },
+    /// SP went above the stack area.
+    StackUnderflow { pc: u16, opcode: u8, sp: u16, cycles: u32 },
+    /// A device reported an error on IN or OUT.
+    Io { pc: u16, opcode: u8, port: u8, message: String, cycles: u32 },
+    Rom(RomError),
+    /// HLT with interrupts disabled, nothing can wake the CPU.
+    HaltDeadlock { pc: u16 },
+}
+
+impl EmulationError {
+    /// Address of the instruction that failed.
+    pub fn pc(&self) -> Option<u16> {
+        match self {
+            EmulationError::IllegalOpcode { pc, .. }
+            | EmulationError::MemoryRange { pc, .. }
+            | EmulationError::StackOverflow { pc, .. }
+            | EmulationError::StackUnderflow { pc, .. }
+            | EmulationError::Io { pc, .. }
+            | EmulationError::HaltDeadlock { pc } => Some(*pc),
+            EmulationError::Rom(_) => None,
+        }
+    }
+
+    /// Cycles of an instruction that ran before the error, 0 when nothing ran.
+    pub fn cycles(&self) -> u32 {
+        match self {
+            EmulationError::MemoryRange { cycles, .. }
+            | EmulationError::StackOverflow { cycles, .. }
+            | EmulationError::StackUnderflow { cycles, .. }
+            | EmulationError::Io { cycles, .. } => *cycles,
+            _ => 0,
+        }
+    }
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = |pc: &u16, opcode: &u8| format!("at {:04x} ({:02x} {})", pc, opcode, mnemonic(*opcode));
+        match self {
+            EmulationError::IllegalOpcode { pc, opcode } => write!(f, "illegal opcode {}", at(pc, opcode)),
+            EmulationError::MemoryRange { pc, opcode, addr, .. } => {
+                write!(f, "access to unmapped address {:04x} {}", addr, at(pc, opcode))
+            }
+            EmulationError::StackOverflow { pc, opcode, sp, .. } => {
+                write!(f, "stack overflow, SP={:04x} {}", sp, at(pc, opcode))
+            }
+            EmulationError::StackUnderflow { pc, opcode, sp, .. } => {
+                write!(f, "stack underflow, SP={:04x} {}", sp, at(pc, opcode))
+            }
+            EmulationError::Io { pc, opcode, port, message, .. } => {
+                write!(f, "I/O error on port {:02x} {}: {}", port, at(pc, opcode), message)
+            }
+            EmulationError::Rom(error) => write!(f, "{}", error),
+            EmulationError::HaltDeadlock { pc } => write!(f, "halted with interrupts disabled at {:04x}", pc),
+        }
+    }
+}
+
+impl std::error::Error for EmulationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmulationError::Rom(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<RomError> for EmulationError {
+    fn from(error: RomError) -> Self {
+        EmulationError::Rom(error)
+    }
+}
+
+/// Mnemonic without operands, which the error does not carry.
+fn mnemonic(opcode: u8) -> String {
+    let text = disasm::decode(&[opcode, 0, 0]).expect("three bytes hold any instruction");
+    text.split(' ').next().unwrap_or_default().to_string()
+}
@@ -1,32 +1,29 @@
 
 pub use crate::cpu::state8080::State8080;
 pub use crate::cpu::ConditionCodes;
+use crate::memory::Bus;
 
 //0x00 0x08 0x10 0x18 0x20 0x28 0x30 0x38
 pub fn nop() {}
 
 //0x01 0x11 0x21 0x31
-pub fn lxi(high: &mut u8, low: &mut u8, pc: &usize, memory: &[u8;16384]) {
-    *high = memory[*pc + 2];
-    *low = memory[*pc + 1];
+pub fn lxi<M: Bus>(high: &mut u8, low: &mut u8, pc: &u16, memory: &mut M) {
+    *high = memory.read(pc.wrapping_add(2));
+    *low = memory.read(pc.wrapping_add(1));
 }
 
 pub fn inr(register: &mut u8, cc: &mut ConditionCodes) {
     *register = register.wrapping_add(1);
-    set_arithmetic_flags(cc, &u16::from(*register));
-    /*cc.z = zero(register);
-    cc.s = sign(register);
-    cc.p = parity(register);
-    cc.ac = (*register & 0xf) ==0;*/
+    set_zsp_flags(cc, register);
+    // carry out of bit 3 only happens when the low nibble wraps to 0, CY is untouched
+    cc.ac = (*register & 0xf) == 0;
 }
 
 pub fn dcr(register: &mut u8, cc: &mut ConditionCodes) {
     *register = register.wrapping_sub(1);
-    set_arithmetic_flags(cc, &u16::from(*register));
-    /*cc.z = zero(register);
-    cc.s = sign(register);
-    cc.p = parity(register);
-    cc.ac = !((*register & 0xf) == 0);*/
+    set_zsp_flags(cc, register);
+    // dcr adds 0xff, so the low nibble carries unless it wrapped to 0xf, CY is untouched
+    cc.ac = (*register & 0xf) != 0xf;
 }
 
 pub fn inx(high: &mut u8, low: &mut u8) {
@@ -46,116 +43,308 @@ pub fn dcx(high: &mut u8, low: &mut u8) {
     if *low == 0xff { *high = high.wrapping_sub(1);}
 }
 
-pub fn stax(register: &u8, high: &u8, low: &u8, memory: &mut [u8;16384]) {
-    let offset: usize = (usize::from(*high) << 8) | usize::from(*low);
-    memory[offset] = *register;
+pub fn stax<M: Bus>(register: &u8, high: &u8, low: &u8, memory: &mut M) {
+    let offset: u16 = (u16::from(*high) << 8) | u16::from(*low);
+    memory.write(offset, *register);
 }
 
-pub fn ldax(register: &mut u8, high: &u8, low: &u8, memory: &[u8;16384]) {
-    let offset: usize = (usize::from(*high) << 8) | usize::from(*low);
-    *register = memory[offset];
+pub fn ldax<M: Bus>(register: &mut u8, high: &u8, low: &u8, memory: &mut M) {
+    let offset: u16 = (u16::from(*high) << 8) | u16::from(*low);
+    *register = memory.read(offset);
 }
 
-pub fn mvi(register: &mut u8, memory: &[u8;16384], pc: &usize) {
-    *register= memory[*pc + 1];
+pub fn mvi<M: Bus>(register: &mut u8, memory: &mut M, pc: &u16) {
+    *register = memory.read(pc.wrapping_add(1));
+}
+
+/// Reads the little endian word at `adr`.
+pub fn read_word<M: Bus>(memory: &mut M, adr: u16) -> u16 {
+    (u16::from(memory.read(adr.wrapping_add(1))) << 8) | u16::from(memory.read(adr))
 }
 
 pub fn mov(tregister: &mut u8, sregister: &u8) {
     *tregister = *sregister;
 }
 
+//0x80-0x87 0xc6
 pub fn add(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    let result : u16 = u16::from(*tregister) + u16::from(*sregister)/* + cc.cy as u16*/;
-    *tregister = result as u8;
-    set_arithmetic_flags(cc, &result)
-    /*cc.cy = result > 0xff;
-    cc.ac = carry(4, *tregister, *sregister, cc.cy);
-    cc.z = zero(tregister);
-    cc.s = sign(tregister);
-    cc.p = parity(tregister);*/
+    add_with_carry(tregister, *sregister, false, cc);
 }
 
+//0x90-0x97 0xd6
 pub fn sub(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    let result : u16 = u16::from(*tregister) - u16::from(*sregister)/* - cc.cy as u16*/;
-    *tregister = result as u8;
-    set_arithmetic_flags(cc, &result)
-    /*cc.cy = result > 0xff;
-    cc.ac = carry(4, *tregister, *sregister, cc.cy);
-    cc.z = zero(tregister);
-    cc.s = sign(tregister);
-    cc.p = parity(tregister);*/
+    *tregister = sub_with_borrow(tregister, sregister, false, cc);
 }
 
+//0xa0-0xa7 0xe6
 pub fn ana(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    let result = *tregister & *sregister;
-    //cc.ac = ((*tregister | *sregister) & 0x08) != 0;
-    *tregister = result;
-    set_arithmetic_flags(cc, &u16::from(result))
-    /*cc.z = zero(tregister);
-    cc.s = sign(tregister);
-    cc.p = parity(tregister);
-    cc.cy = false;*/
+    // the 8080 sets AC to the OR of bit 3 of both operands for logical AND
+    cc.ac = ((*tregister | *sregister) & 0x08) != 0;
+    *tregister &= *sregister;
+    set_zsp_flags(cc, tregister);
+    cc.cy = false;
 }
 
+//0xa8-0xaf 0xee
 pub fn xra(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    *tregister = *tregister ^ *sregister;
-    set_arithmetic_flags(cc, &u16::from(*tregister));
-    /*cc.z = zero(tregister);
-    cc.s = sign(tregister);
-    cc.p = parity(tregister);
+    *tregister ^= *sregister;
+    set_zsp_flags(cc, tregister);
     cc.cy = false;
-    cc.ac = false;*/
+    cc.ac = false;
 }
 
+//0xb0-0xb7 0xf6
 pub fn ora(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    *tregister = *tregister | *sregister;
-    set_arithmetic_flags(cc, &u16::from(*tregister));
-    /*cc.z = zero(tregister);
-    cc.s = sign(tregister);
-    cc.p = parity(tregister);
+    *tregister |= *sregister;
+    set_zsp_flags(cc, tregister);
     cc.cy = false;
-    cc.ac = false;*/
+    cc.ac = false;
 }
 
+//0xb8-0xbf 0xfe
 pub fn cmp(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
-    let result = u16::from(*tregister).wrapping_sub(u16::from(*sregister));
-    //cc.cy = result >> 8 != 0;
-    set_arithmetic_flags(cc, &result);
-    /*let result = tregister.wrapping_sub(*sregister);
-    cc.ac = (*tregister ^ result ^ *sregister) & 0x10 == 0;
-    cc.z = zero(&result);
-    cc.s = sign(&result);
-    cc.p = parity(&result);*/
+    sub_with_borrow(tregister, sregister, false, cc);
 }
 
-fn parity(x: &u8) -> bool {
-    let mut one_bits: u8 = 0;
-    for i in 0..8 {
-        one_bits += (x >> i) & 0x1;
+fn carry(bit_no: u8, a: u8, b: u8, cy: bool) -> bool {
+    let result: u16 = a as u16 + b as u16 + cy as u16;
+    let carry: u16 = result ^ a as u16 ^ b as u16;
+    (carry & (1 << bit_no as u16)) != 0
+}
+
+//0x88-0x8f 0xce
+pub fn adc(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
+    let cy = cc.cy;
+    add_with_carry(tregister, *sregister, cy, cc);
+}
+
+//0x98-0x9f 0xde
+pub fn sbb(tregister: &mut u8, sregister: &u8, cc: &mut ConditionCodes) {
+    let cy = cc.cy;
+    *tregister = sub_with_borrow(tregister, sregister, cy, cc);
+}
+
+fn add_with_carry(tregister: &mut u8, value: u8, cy: bool, cc: &mut ConditionCodes) {
+    let result: u16 = u16::from(*tregister) + u16::from(value) + u16::from(cy);
+    cc.ac = carry(4, *tregister, value, cy);
+    cc.cy = result > 0xff;
+    *tregister = result as u8;
+    set_zsp_flags(cc, tregister);
+}
+
+// The 8080 subtracts by adding the one's complement of the operand and the inverted borrow.
+// AC is the carry out of bit 3 of that addition, CY is the inverted carry out of bit 7 (borrow).
+fn sub_with_borrow(tregister: &u8, sregister: &u8, borrow: bool, cc: &mut ConditionCodes) -> u8 {
+    let result: u16 = u16::from(*tregister) + u16::from(!*sregister) + u16::from(!borrow);
+    cc.ac = carry(4, *tregister, !*sregister, !borrow);
+    cc.cy = result <= 0xff;
+    let result = result as u8;
+    set_zsp_flags(cc, &result);
+    result
+}
+
+//0x27
+pub fn daa(a: &mut u8, cc: &mut ConditionCodes) {
+    let mut correction: u8 = 0;
+    let mut cy = cc.cy;
+    if (*a & 0x0f) > 9 || cc.ac {
+        correction |= 0x06;
     }
-    return (one_bits & 0x1) != 0;
+    if (*a >> 4) > 9 || ((*a >> 4) >= 9 && (*a & 0x0f) > 9) || cc.cy {
+        correction |= 0x60;
+        cy = true;
+    }
+    cc.ac = carry(4, *a, correction, false);
+    *a = a.wrapping_add(correction);
+    set_zsp_flags(cc, a);
+    cc.cy = cy;
 }
 
-fn zero(x: &u8) -> bool {
-    return *x == 0;
+//0x07
+pub fn rlc(a: &mut u8, cc: &mut ConditionCodes) {
+    cc.cy = (*a >> 7) != 0;
+    *a = a.rotate_left(1);
 }
 
-fn sign(x: &u8) -> bool {
-    return (*x & 0x80) != 0;
+//0x0f
+pub fn rrc(a: &mut u8, cc: &mut ConditionCodes) {
+    cc.cy = (*a & 0x1) != 0;
+    *a = a.rotate_right(1);
 }
 
-fn carry(bit_no: u8, a: u8, b: u8, cy: bool) -> bool {
-    let result: u16 = a as u16 + b as u16 + cy as u16;
-    let carry: u16 = result ^ a as u16 ^ b as u16;
-    return (carry & (1 << bit_no as u16)) != 0;
+//0x17
+pub fn ral(a: &mut u8, cc: &mut ConditionCodes) {
+    let cy: bool = cc.cy;
+    cc.cy = (*a >> 7) != 0;
+    *a = (*a << 1) | cy as u8;
+}
+
+//0x1f
+pub fn rar(a: &mut u8, cc: &mut ConditionCodes) {
+    let cy: bool = cc.cy;
+    cc.cy = *a & 1 != 0;
+    *a = (*a >> 1) | ((cy as u8) << 7);
+}
+
+pub fn push<M: Bus>(high: &u8, low: &u8, sp: &mut u16, memory: &mut M) {
+    memory.write(sp.wrapping_sub(1), *high);
+    memory.write(sp.wrapping_sub(2), *low);
+    *sp = sp.wrapping_sub(2);
+}
+
+pub fn pop<M: Bus>(high: &mut u8, low: &mut u8, sp: &mut u16, memory: &mut M) {
+    *low = memory.read(*sp);
+    *high = memory.read(sp.wrapping_add(1));
+    *sp = sp.wrapping_add(2);
+}
+
+//0xc3 0xcb and conditional jumps
+pub fn jmp<M: Bus>(pc: &mut u16, memory: &mut M, adr: &u16) {
+    *pc = read_word(memory, adr.wrapping_add(1));
+}
+
+//0xcd 0xdd 0xed 0xfd and conditional calls
+pub fn call<M: Bus>(pc: &mut u16, sp: &mut u16, memory: &mut M, adr: &u16) {
+    let ret = pc.to_be_bytes();
+    push(&ret[0], &ret[1], sp, memory);
+    jmp(pc, memory, adr);
+}
+
+//0xc9 0xd9 and conditional returns
+pub fn ret<M: Bus>(pc: &mut u16, sp: &mut u16, memory: &mut M) {
+    let (mut high, mut low) = (0, 0);
+    pop(&mut high, &mut low, sp, memory);
+    *pc = (u16::from(high) << 8) | u16::from(low);
+}
+
+//0xc7 0xcf 0xd7 0xdf 0xe7 0xef 0xf7 0xff
+pub fn rst<M: Bus>(n: u8, pc: &mut u16, sp: &mut u16, memory: &mut M) {
+    let ret = pc.to_be_bytes();
+    push(&ret[0], &ret[1], sp, memory);
+    *pc = u16::from(n) * 8;
+}
+
+fn parity(x: &u8) -> bool {
+    x.count_ones() & 1 == 0
+}
+
+pub fn set_zsp_flags(cc: &mut ConditionCodes, val: &u8) {
+    cc.z = *val == 0;
+    cc.s = (*val & 0x80) != 0;
+    cc.p = parity(val);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
+    fn flags(cy: bool, ac: bool) -> ConditionCodes {
+        ConditionCodes {
+            z: false,
+            s: false,
+            p: false,
+            cy,
+            ac,
+            pad: 0,
+        }
+    }
+
+    #[test]
+    fn daa_adjusts_both_nibbles() {
+        let (mut a, mut cc) = (0x9b, flags(false, false));
+        daa(&mut a, &mut cc);
+        assert_eq!(a, 0x01);
+        assert!(cc.cy && cc.ac && !cc.z && !cc.s && !cc.p);
 
-pub fn set_arithmetic_flags(cc: &mut ConditionCodes, val: &u16){
-    cc.z = (*val & 0xff) == 0;
-    cc.s = 0x80 == (*val & 0x80);
-    cc.p = (*val & 0xff).count_ones() % 2 == 0;
-    cc.cy = *val >> 8 != 0;
-    cc.ac = cc.cy;
-}
\ No newline at end of file
+        // 0x15 + 0x27 = 0x3c, adjusted to BCD 42
+        let (mut a, mut cc) = (0x3c, flags(false, false));
+        daa(&mut a, &mut cc);
+        assert_eq!(a, 0x42);
+        assert!(!cc.cy && cc.ac);
+
+        // a carry from a previous addition keeps CY set
+        let (mut a, mut cc) = (0x00, flags(true, false));
+        daa(&mut a, &mut cc);
+        assert_eq!(a, 0x60);
+        assert!(cc.cy && !cc.ac);
+    }
+
+    #[test]
+    fn inr_and_dcr_keep_carry() {
+        let (mut r, mut cc) = (0xff, flags(true, false));
+        inr(&mut r, &mut cc);
+        assert_eq!(r, 0x00);
+        assert!(cc.cy && cc.z && cc.ac && cc.p);
+
+        let (mut r, mut cc) = (0x00, flags(false, true));
+        dcr(&mut r, &mut cc);
+        assert_eq!(r, 0xff);
+        assert!(!cc.cy && cc.s && !cc.ac);
+
+        let (mut r, mut cc) = (0x10, flags(true, false));
+        dcr(&mut r, &mut cc);
+        assert_eq!(r, 0x0f);
+        assert!(cc.cy && !cc.ac);
+    }
+
+    #[test]
+    fn ana_sets_ac_from_bit_3_of_operands() {
+        for (a, r, ac) in [(0x08, 0x00, true), (0x00, 0x08, true), (0xf7, 0xf7, false), (0x0f, 0xf0, true)] {
+            let (mut a, mut cc) = (a, flags(true, !ac));
+            ana(&mut a, &r, &mut cc);
+            assert_eq!(cc.ac, ac, "ANA {:02x}", r);
+            assert!(!cc.cy);
+        }
+        // the logical ops other than ANA clear AC
+        let (mut a, mut cc) = (0x08, flags(true, true));
+        ora(&mut a, &0x08, &mut cc);
+        assert!(!cc.ac && !cc.cy);
+    }
+
+    #[test]
+    fn subtraction_borrows() {
+        let (mut a, mut cc) = (0x00, flags(false, true));
+        sub(&mut a, &0x01, &mut cc);
+        assert_eq!(a, 0xff);
+        assert!(cc.cy && !cc.ac && cc.s && cc.p);
+
+        // no borrow out of the low nibble sets AC
+        let (mut a, mut cc) = (0x3e, flags(true, false));
+        sub(&mut a, &0x3e, &mut cc);
+        assert_eq!(a, 0x00);
+        assert!(!cc.cy && cc.ac && cc.z);
+
+        // SBB subtracts the borrow too
+        let (mut a, mut cc) = (0x04, flags(true, false));
+        sbb(&mut a, &0x02, &mut cc);
+        assert_eq!(a, 0x01);
+        assert!(!cc.cy);
+        let (mut a, mut cc) = (0x02, flags(true, false));
+        sbb(&mut a, &0x02, &mut cc);
+        assert_eq!(a, 0xff);
+        assert!(cc.cy);
+
+        // CMP leaves A alone and sets CY when A is below the operand
+        let (mut a, mut cc) = (0x0a, flags(false, false));
+        cmp(&mut a, &0x05, &mut cc);
+        assert_eq!(a, 0x0a);
+        assert!(!cc.cy && !cc.z);
+        cmp(&mut a, &0x0a, &mut cc);
+        assert!(!cc.cy && cc.z);
+        cmp(&mut a, &0x0b, &mut cc);
+        assert!(cc.cy && !cc.z);
+    }
+
+    #[test]
+    fn addition_carries() {
+        let (mut a, mut cc) = (0x2e, flags(false, false));
+        add(&mut a, &0x74, &mut cc);
+        assert_eq!(a, 0xa2);
+        assert!(!cc.cy && cc.ac && cc.s && !cc.p);
+
+        let (mut a, mut cc) = (0xff, flags(true, false));
+        adc(&mut a, &0x00, &mut cc);
+        assert_eq!(a, 0x00);
+        assert!(cc.cy && cc.ac && cc.z);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::{Input, Snapshot, StateError};
+
 pub struct ConditionCodes {
     pub z: bool,
     pub s: bool,
@@ -7,6 +9,37 @@ pub struct ConditionCodes {
     pub pad: u8,
 }
 
+impl ConditionCodes {
+    /// Flags packed into the low byte of PSW: S Z 0 AC 0 P 1 CY.
+    pub fn psw(&self) -> u8 {
+        let mut psw: u8 = 0b10;
+        if self.cy {
+            psw |= 0b1;
+        }
+        if self.p {
+            psw |= 0b100;
+        }
+        if self.ac {
+            psw |= 0b10000;
+        }
+        if self.z {
+            psw |= 0b1000000;
+        }
+        if self.s {
+            psw |= 0b10000000;
+        }
+        psw
+    }
+
+    pub fn set_psw(&mut self, psw: u8) {
+        self.cy = (psw & 0b1) != 0;
+        self.p = (psw & 0b100) != 0;
+        self.ac = (psw & 0b10000) != 0;
+        self.z = (psw & 0b1000000) != 0;
+        self.s = (psw & 0b10000000) != 0;
+    }
+}
+
 pub struct State8080 {
     pub a: u8,
     pub b: u8,
@@ -17,7 +50,42 @@ pub struct State8080 {
     pub l: u8,
     pub sp: u16,
     pub pc: u16,
-    pub memory: [u8;16384],
     pub cc: ConditionCodes,
     pub int_enable: bool,
-}
\ No newline at end of file
+    /// Set by EI, interrupts are not accepted before the instruction after EI has run.
+    pub int_delay: bool,
+    pub halted: bool,
+    /// Instruction the interrupting device puts on the data bus once the interrupt is
+    /// acknowledged. Stays pending while interrupts are disabled.
+    pub interrupt_request: Option<[u8; 3]>,
+}
+
+impl Snapshot for State8080 {
+    fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&[self.a, self.b, self.c, self.d, self.e, self.h, self.l, self.cc.psw()]);
+        out.extend_from_slice(&self.sp.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&[
+            u8::from(self.int_enable),
+            u8::from(self.int_delay),
+            u8::from(self.halted),
+            u8::from(self.interrupt_request.is_some()),
+        ]);
+        out.extend_from_slice(&self.interrupt_request.unwrap_or_default());
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        let registers = input.bytes(8)?;
+        [self.a, self.b, self.c, self.d, self.e, self.h, self.l] = registers[..7].try_into().unwrap();
+        self.cc.set_psw(registers[7]);
+        self.sp = input.u16()?;
+        self.pc = input.u16()?;
+        self.int_enable = input.bool()?;
+        self.int_delay = input.bool()?;
+        self.halted = input.bool()?;
+        let pending = input.bool()?;
+        let instruction: [u8; 3] = input.bytes(3)?.try_into().unwrap();
+        self.interrupt_request = pending.then_some(instruction);
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use crate::cpu::{Cpu8080, State8080};
+use crate::disasm;
+use crate::io::IoBus;
+use crate::memory::Bus;
+use crate::report::{self, Reporter};
+use crate::watch::{AccessKind, MemoryAccess, Watch, Watchpoint};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+pub const HELP: &str = "\
+commands (addresses and values are hex):
+  s, step [N]          execute N instructions (default 1)
+  n, next              step over CALL and RST
+  c, continue          run until a breakpoint
+  u, until ADDR        run until PC reaches ADDR
+  b, break [ADDR]      set a breakpoint, or list them
+  d, delete ADDR       remove a breakpoint
+  w, watch [SPEC]      set a watchpoint, or list them. SPEC is ADDR[-END]
+                       followed by r, w (default) or rw, =VALUE and log
+  unwatch N            remove watchpoint N
+  r, regs              show registers and flags
+  x, peek ADDR [LEN]   dump LEN bytes of memory (default 64)
+  poke ADDR BYTE...    write bytes to memory
+  dump [FILE]          write a report with registers, history and memory
+  q, quit              stop emulation
+  h, help              show this help
+an empty line repeats the previous command";
+
+/// What the emulation loop should do after the debugger returns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resume {
+    Run,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Paused,
+    /// instructions left before pausing
+    Stepping(u64),
+    Running,
+}
+
+/// Breakpoints and run control for an interactive debugging session.
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    /// temporary breakpoint of `next` and `until`
+    run_to: Option<u16>,
+    mode: Mode,
+    /// kind and address of the watchpoint that stopped execution
+    watch_hit: Option<(Watch, u16)>,
+    last_command: String,
+}
+
+impl Debugger {
+    /// A debugger that stops before the first instruction when `paused` is set,
+    /// otherwise only at breakpoints.
+    pub fn new(paused: bool) -> Self {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            run_to: None,
+            mode: if paused { Mode::Paused } else { Mode::Running },
+            watch_hit: None,
+            last_command: String::new(),
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = &u16> {
+        self.breakpoints.iter()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// The watchpoint kind and address that stopped execution, if it was one.
+    pub fn take_watch_hit(&mut self) -> Option<(Watch, u16)> {
+        self.watch_hit.take()
+    }
+
+    /// Checks the memory accesses of the instruction `bytes` just executed at `pc`
+    /// against the watchpoints. Hits are logged to `out`, and unless all of them
+    /// are log only the debugger stops before the next instruction.
+    pub fn check_accesses<W: Write>(
+        &mut self,
+        pc: u16,
+        bytes: [u8; 3],
+        accesses: &[MemoryAccess],
+        out: &mut W,
+    ) -> io::Result<()> {
+        let fetch = |addr: u16| addr.wrapping_sub(pc) < disasm::size(bytes[0]) as u16;
+        for access in accesses {
+            // reads of the instruction's own bytes are fetches, not data reads
+            if access.kind == AccessKind::Read && fetch(access.addr) {
+                continue;
+            }
+            for watchpoint in self.watchpoints.iter().filter(|w| w.matches(access)) {
+                let text = disasm::decode(&bytes).expect("three bytes hold any instruction");
+                match access.kind {
+                    AccessKind::Read => writeln!(
+                        out,
+                        "watch {}: read {:04x} = {:02x} at {:04x}  {}",
+                        watchpoint, access.addr, access.new, pc, text
+                    )?,
+                    AccessKind::Write => writeln!(
+                        out,
+                        "watch {}: write {:04x} {:02x} -> {:02x} at {:04x}  {}",
+                        watchpoint, access.addr, access.old, access.new, pc, text
+                    )?,
+                }
+                if !watchpoint.log_only {
+                    self.mode = Mode::Paused;
+                    self.watch_hit = Some((watchpoint.watch, access.addr));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops at the next instruction, e.g. on a hotkey or a watchpoint.
+    pub fn pause(&mut self) {
+        self.mode = Mode::Paused;
+    }
+
+    /// Runs until a breakpoint or watchpoint.
+    pub fn resume(&mut self) {
+        self.mode = Mode::Running;
+    }
+
+    /// Executes one instruction and stops again.
+    pub fn single_step(&mut self) {
+        self.mode = Mode::Stepping(0);
+    }
+
+    /// Drops all breakpoints and watchpoints and lets the program run.
+    pub fn detach(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.run_to = None;
+        self.mode = Mode::Running;
+    }
+
+    /// Called before every instruction, returns whether the debugger wants to stop at `pc`.
+    pub fn should_break(&mut self, pc: u16) -> bool {
+        if self.run_to == Some(pc) {
+            self.run_to = None;
+            self.mode = Mode::Paused;
+        }
+        if self.breakpoints.contains(&pc) {
+            self.mode = Mode::Paused;
+        }
+        match self.mode {
+            Mode::Paused => true,
+            Mode::Stepping(0) => {
+                self.mode = Mode::Paused;
+                true
+            }
+            Mode::Stepping(n) => {
+                self.mode = Mode::Stepping(n - 1);
+                false
+            }
+            Mode::Running => false,
+        }
+    }
+
+    /// Shows the current instruction and reads commands from `input` until one of them
+    /// resumes execution.
+    pub fn prompt<M: Bus, I: IoBus, R: BufRead, W: Write>(
+        &mut self,
+        cpu: &mut Cpu8080<M, I>,
+        reporter: &Reporter,
+        input: &mut R,
+        out: &mut W,
+    ) -> io::Result<Resume> {
+        writeln!(out, "{}", self.location(cpu))?;
+        loop {
+            write!(out, "(8080) ")?;
+            out.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(Resume::Quit);
+            }
+            let mut line = line.trim().to_string();
+            if line.is_empty() {
+                line = self.last_command.clone();
+            } else {
+                self.last_command = line.clone();
+            }
+            match self.execute(cpu, reporter, &line, out) {
+                Ok(Some(resume)) => return Ok(resume),
+                Ok(None) => {}
+                Err(message) => writeln!(out, "{}", message)?,
+            }
+        }
+    }
+
+    /// Runs one command line, returning `Some` when it resumes execution.
+    pub fn execute<M: Bus, I: IoBus, W: Write>(
+        &mut self,
+        cpu: &mut Cpu8080<M, I>,
+        reporter: &Reporter,
+        line: &str,
+        out: &mut W,
+    ) -> Result<Option<Resume>, String> {
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Ok(None),
+        };
+        let args: Vec<&str> = words.collect();
+        let io_error = |e: io::Error| e.to_string();
+        match command {
+            "s" | "step" => {
+                let count = match args.first() {
+                    Some(n) => n.parse::<u64>().map_err(|_| format!("'{}' is not a count", n))?,
+                    None => 1,
+                };
+                if count == 0 {
+                    return Err("step count must be at least 1".to_string());
+                }
+                // the current instruction runs right away, pause before the one after the last
+                self.mode = Mode::Stepping(count - 1);
+                Ok(Some(Resume::Run))
+            }
+            "n" | "next" => {
+                let pc = cpu.pc();
+                let opcode = cpu.memory().peek(pc);
+                if is_call(opcode) {
+                    self.run_to = Some(pc.wrapping_add(disasm::size(opcode) as u16));
+                    self.mode = Mode::Running;
+                } else {
+                    self.mode = Mode::Stepping(0);
+                }
+                Ok(Some(Resume::Run))
+            }
+            "c" | "continue" => {
+                self.mode = Mode::Running;
+                Ok(Some(Resume::Run))
+            }
+            "u" | "until" => {
+                self.run_to = Some(parse_hex(args.first().copied())?);
+                self.mode = Mode::Running;
+                Ok(Some(Resume::Run))
+            }
+            "b" | "break" => {
+                if args.is_empty() {
+                    for addr in &self.breakpoints {
+                        writeln!(out, "breakpoint at {:04x}", addr).map_err(io_error)?;
+                    }
+                } else {
+                    let addr = parse_hex(args.first().copied())?;
+                    self.add_breakpoint(addr);
+                    writeln!(out, "breakpoint at {:04x}", addr).map_err(io_error)?;
+                }
+                Ok(None)
+            }
+            "d" | "delete" => {
+                let addr = parse_hex(args.first().copied())?;
+                if !self.remove_breakpoint(addr) {
+                    return Err(format!("no breakpoint at {:04x}", addr));
+                }
+                Ok(None)
+            }
+            "w" | "watch" => {
+                if args.is_empty() {
+                    for (n, watchpoint) in self.watchpoints.iter().enumerate() {
+                        writeln!(out, "{}: {}", n, watchpoint).map_err(io_error)?;
+                    }
+                } else {
+                    let spec = args.join(" ");
+                    let watchpoint = Watchpoint::parse(&spec, |text| parse_hex(Some(text)).map(u64::from))?;
+                    writeln!(out, "{}: {}", self.watchpoints.len(), watchpoint).map_err(io_error)?;
+                    self.add_watchpoint(watchpoint);
+                }
+                Ok(None)
+            }
+            "unwatch" => {
+                let n = args.first().ok_or("missing watchpoint number")?;
+                let n = n.parse::<usize>().map_err(|_| format!("'{}' is not a watchpoint number", n))?;
+                if n >= self.watchpoints.len() {
+                    return Err(format!("no watchpoint {}", n));
+                }
+                self.watchpoints.remove(n);
+                Ok(None)
+            }
+            "r" | "regs" => {
+                writeln!(out, "{}", registers(cpu.state())).map_err(io_error)?;
+                Ok(None)
+            }
+            "x" | "peek" => {
+                let addr = parse_hex(args.first().copied())?;
+                let len = match args.get(1) {
+                    Some(len) => parse_hex(Some(*len))?,
+                    None => 64,
+                };
+                let dump = report::hexdump(cpu.memory(), addr, u32::from(len), &[], false);
+                write!(out, "{}", dump).map_err(io_error)?;
+                Ok(None)
+            }
+            "poke" => {
+                let addr = parse_hex(args.first().copied())?;
+                if args.len() < 2 {
+                    return Err("usage: poke ADDR BYTE...".to_string());
+                }
+                for (i, byte) in args[1..].iter().enumerate() {
+                    let value = parse_hex(Some(*byte))?;
+                    let value = u8::try_from(value).map_err(|_| format!("{:x} is not a byte", value))?;
+                    cpu.memory_mut().write(addr.wrapping_add(i as u16), value);
+                }
+                Ok(None)
+            }
+            "dump" => {
+                let path = args.first().map(PathBuf::from).unwrap_or_else(|| reporter.dump_path());
+                reporter
+                    .write(cpu, "requested in the debugger", &path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                writeln!(out, "report written to {}", path.display()).map_err(io_error)?;
+                Ok(None)
+            }
+            "q" | "quit" => Ok(Some(Resume::Quit)),
+            "h" | "help" => {
+                writeln!(out, "{}", HELP).map_err(io_error)?;
+                Ok(None)
+            }
+            _ => Err(format!("unknown command '{}', try help", command)),
+        }
+    }
+
+    /// The instruction at PC, disassembled.
+    pub fn location<M: Bus, I: IoBus>(&self, cpu: &Cpu8080<M, I>) -> String {
+        disasm::disassemble(cpu.memory(), cpu.pc()).to_string()
+    }
+}
+
+/// CALL, conditional calls and RST, which `next` steps over.
+fn is_call(opcode: u8) -> bool {
+    matches!(opcode, 0xcd | 0xdd | 0xed | 0xfd) || opcode & 0xc7 == 0xc4 || opcode & 0xc7 == 0xc7
+}
+
+/// Registers, register pairs and flags on one line.
+pub fn registers(state: &State8080) -> String {
+    let cc = &state.cc;
+    let flag = |set: bool, name: char| if set { name } else { '-' };
+    format!(
+        "A={:02x} BC={:02x}{:02x} DE={:02x}{:02x} HL={:02x}{:02x} SP={:04x} PC={:04x} flags={}{}{}{}{} int={} halted={}",
+        state.a,
+        state.b,
+        state.c,
+        state.d,
+        state.e,
+        state.h,
+        state.l,
+        state.sp,
+        state.pc,
+        flag(cc.s, 'S'),
+        flag(cc.z, 'Z'),
+        flag(cc.ac, 'A'),
+        flag(cc.p, 'P'),
+        flag(cc.cy, 'C'),
+        if state.int_enable { "on" } else { "off" },
+        state.halted
+    )
+}
+
+/// Parses a hex number with an optional `$` or `0x` prefix.
+pub fn parse_hex(text: Option<&str>) -> Result<u16, String> {
+    let text = text.ok_or("missing address")?;
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a hex number", text))
+}
+
+/// Parses decimal, `0x1f` or `$1f`.
+pub fn parse_number(text: &str) -> Result<u64, String> {
+    let parsed = if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')) {
+        u64::from_str_radix(hex, 16)
+    } else {
+        text.parse::<u64>()
+    };
+    parsed.map_err(|_| format!("'{}' is not a number", text))
+}
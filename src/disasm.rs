@@ -0,0 +1,134 @@
+use crate::cpu::opcodes;
+use crate::memory::Bus;
+use std::fmt;
+
+const REGISTERS: [&str; 8] = ["B", "C", "D", "E", "H", "L", "M", "A"];
+const PAIRS: [&str; 4] = ["B", "D", "H", "SP"];
+const STACK_PAIRS: [&str; 4] = ["B", "D", "H", "PSW"];
+const CONDITIONS: [&str; 8] = ["NZ", "Z", "NC", "C", "PO", "PE", "P", "M"];
+const ALU: [&str; 8] = ["ADD", "ADC", "SUB", "SBB", "ANA", "XRA", "ORA", "CMP"];
+const ALU_IMMEDIATE: [&str; 8] = ["ADI", "ACI", "SUI", "SBI", "ANI", "XRI", "ORI", "CPI"];
+const ROTATES: [&str; 8] = ["RLC", "RRC", "RAL", "RAR", "DAA", "CMA", "STC", "CMC"];
+
+/// One decoded instruction with its address and encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl fmt::Display for Instruction {
+    /// `0002  cd 08 00  CALL $0008`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(f, "{:04x}  {:<8}  {}", self.addr, bytes.join(" "), self.text)
+    }
+}
+
+/// Length of the instruction starting with `opcode`.
+pub fn size(opcode: u8) -> usize {
+    usize::from(opcodes::SIZE[usize::from(opcode)])
+}
+
+/// Intel mnemonic of the instruction at the start of `bytes`, e.g. `LXI B,$1234`.
+/// Undocumented aliases are marked with `*`. Returns `None` if the operands are cut off.
+pub fn decode(bytes: &[u8]) -> Option<String> {
+    let opcode = *bytes.first()?;
+    if bytes.len() < size(opcode) {
+        return None;
+    }
+    let d8 = || format!("${:02X}", bytes[1]);
+    let d16 = || format!("${:02X}{:02X}", bytes[2], bytes[1]);
+    let y = usize::from((opcode >> 3) & 7);
+    let p = y >> 1;
+    let odd = y & 1 == 1;
+    let text = match (opcode >> 6, opcode & 7) {
+        (0, 0) if y == 0 => "NOP".to_string(),
+        (0, 0) => "*NOP".to_string(),
+        (0, 1) if odd => format!("DAD {}", PAIRS[p]),
+        (0, 1) => format!("LXI {},{}", PAIRS[p], d16()),
+        (0, 2) => match y {
+            0 => "STAX B".to_string(),
+            1 => "LDAX B".to_string(),
+            2 => "STAX D".to_string(),
+            3 => "LDAX D".to_string(),
+            4 => format!("SHLD {}", d16()),
+            5 => format!("LHLD {}", d16()),
+            6 => format!("STA {}", d16()),
+            _ => format!("LDA {}", d16()),
+        },
+        (0, 3) if odd => format!("DCX {}", PAIRS[p]),
+        (0, 3) => format!("INX {}", PAIRS[p]),
+        (0, 4) => format!("INR {}", REGISTERS[y]),
+        (0, 5) => format!("DCR {}", REGISTERS[y]),
+        (0, 6) => format!("MVI {},{}", REGISTERS[y], d8()),
+        (0, _) => ROTATES[y].to_string(),
+        (1, _) if opcode == 0x76 => "HLT".to_string(),
+        (1, z) => format!("MOV {},{}", REGISTERS[y], REGISTERS[usize::from(z)]),
+        (2, z) => format!("{} {}", ALU[y], REGISTERS[usize::from(z)]),
+        (_, 0) => format!("R{}", CONDITIONS[y]),
+        (_, 1) => match y {
+            1 => "RET".to_string(),
+            3 => "*RET".to_string(),
+            5 => "PCHL".to_string(),
+            7 => "SPHL".to_string(),
+            _ => format!("POP {}", STACK_PAIRS[p]),
+        },
+        (_, 2) => format!("J{} {}", CONDITIONS[y], d16()),
+        (_, 3) => match y {
+            0 => format!("JMP {}", d16()),
+            1 => format!("*JMP {}", d16()),
+            2 => format!("OUT {}", d8()),
+            3 => format!("IN {}", d8()),
+            4 => "XTHL".to_string(),
+            5 => "XCHG".to_string(),
+            6 => "DI".to_string(),
+            _ => "EI".to_string(),
+        },
+        (_, 4) => format!("C{} {}", CONDITIONS[y], d16()),
+        (_, 5) => match y {
+            1 => format!("CALL {}", d16()),
+            3 | 5 | 7 => format!("*CALL {}", d16()),
+            _ => format!("PUSH {}", STACK_PAIRS[p]),
+        },
+        (_, 6) => format!("{} {}", ALU_IMMEDIATE[y], d8()),
+        _ => format!("RST {}", y),
+    };
+    Some(text)
+}
+
+/// Decodes the instruction at `addr`, wrapping around the end of the address space.
+pub fn disassemble<M: Bus>(memory: &M, addr: u16) -> Instruction {
+    let opcode = memory.peek(addr);
+    let bytes: Vec<u8> = (0..size(opcode) as u16)
+        .map(|i| memory.peek(addr.wrapping_add(i)))
+        .collect();
+    let text = decode(&bytes).expect("all operand bytes were read");
+    Instruction { addr, bytes, text }
+}
+
+/// Decodes a whole image loaded at `origin`. A trailing instruction whose operands
+/// are cut off is shown as `DB` bytes.
+pub fn disassemble_image(data: &[u8], origin: u16) -> Vec<Instruction> {
+    let mut listing = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        let rest = &data[offset..];
+        let (len, text) = match decode(rest) {
+            Some(text) => (size(rest[0]), text),
+            None => {
+                let bytes: Vec<String> = rest.iter().map(|b| format!("${:02X}", b)).collect();
+                (rest.len(), format!("DB {}", bytes.join(",")))
+            }
+        };
+        listing.push(Instruction {
+            addr,
+            bytes: rest[..len].to_vec(),
+            text,
+        });
+        offset += len;
+    }
+    listing
+}
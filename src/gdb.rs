@@ -0,0 +1,406 @@
+use crate::cpu::Cpu8080;
+use crate::debugger::{Debugger, Resume};
+use crate::io::IoBus;
+use crate::memory::Bus;
+use crate::watch::{Watch, Watchpoint};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.gnu.gdb.i8080.cpu">
+    <reg name="af" bitsize="16" type="int" regnum="0"/>
+    <reg name="bc" bitsize="16" type="int"/>
+    <reg name="de" bitsize="16" type="int"/>
+    <reg name="hl" bitsize="16" type="int"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+/// Largest `m` request answered in one packet.
+const MAX_MEMORY_READ: usize = 0x800;
+
+/// A GDB remote serial protocol server with one client. It reports six 16 bit
+/// registers, AF, BC, DE, HL, SP and PC, and drives a `Debugger`, which keeps the
+/// breakpoints and watchpoints and decides when the CPU stops.
+pub struct GdbStub {
+    stream: Option<TcpStream>,
+    /// bytes received but not yet parsed
+    pending: VecDeque<u8>,
+    /// the client is waiting for a stop reply
+    running: bool,
+}
+
+impl GdbStub {
+    /// Listens on `addr` and waits for one client to connect.
+    pub fn accept<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            stream: Some(stream),
+            pending: VecDeque::new(),
+            running: false,
+        })
+    }
+
+    pub fn connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Checks without blocking whether the client sent an interrupt (Ctrl-C) while
+    /// the CPU is running.
+    pub fn interrupted(&mut self) -> bool {
+        let Some(stream) = self.stream.as_mut() else {
+            return false;
+        };
+        let mut buf = [0; 256];
+        if stream.set_nonblocking(true).is_ok() {
+            while let Ok(n @ 1..) = stream.read(&mut buf) {
+                self.pending.extend(&buf[..n]);
+            }
+            let _ = stream.set_nonblocking(false);
+        }
+        match self.pending.iter().position(|b| *b == 0x03) {
+            Some(i) => {
+                self.pending.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reports the end of the program with its exit `status` and closes the connection.
+    pub fn exited(&mut self, status: u8) {
+        if self.connected() {
+            let _ = self.send(&format!("W{:02x}", status));
+            self.stream = None;
+        }
+    }
+
+    /// Called when the debugger stops: reports the stop to the client and answers
+    /// packets until it resumes, detaches or kills the target. After a failed read
+    /// or write the client is dropped and the debugger detached.
+    pub fn serve<M: Bus, I: IoBus>(&mut self, cpu: &mut Cpu8080<M, I>, debugger: &mut Debugger) -> io::Result<Resume> {
+        if self.stream.is_none() {
+            debugger.detach();
+            return Ok(Resume::Run);
+        }
+        if self.running {
+            self.running = false;
+            let reply = stop_reply(debugger.take_watch_hit());
+            self.send(&reply)?;
+        }
+        loop {
+            let packet = match self.receive() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => {
+                    // the client went away, keep running without it
+                    self.stream = None;
+                    debugger.detach();
+                    return Ok(Resume::Run);
+                }
+                Err(e) => {
+                    self.stream = None;
+                    debugger.detach();
+                    return Err(e);
+                }
+            };
+            match self.handle(&packet, cpu, debugger)? {
+                Some(Resume::Run) if !self.connected() => return Ok(Resume::Run),
+                Some(Resume::Run) => {
+                    self.running = true;
+                    return Ok(Resume::Run);
+                }
+                Some(Resume::Quit) => return Ok(Resume::Quit),
+                None => {}
+            }
+        }
+    }
+
+    /// Answers one packet, returning `Some` when it resumes or ends the session.
+    fn handle<M: Bus, I: IoBus>(
+        &mut self,
+        packet: &str,
+        cpu: &mut Cpu8080<M, I>,
+        debugger: &mut Debugger,
+    ) -> io::Result<Option<Resume>> {
+        let split = if packet.is_char_boundary(1) { packet.split_at(1) } else { ("", packet) };
+        let reply = match split {
+            ("?", _) => "S05".to_string(),
+            ("g", _) => registers(cpu).iter().map(|r| word_hex(*r)).collect(),
+            ("G", hex) => match parse_words(hex) {
+                Some(words) if words.len() == 6 => {
+                    set_registers(cpu, &words);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            ("p", n) => match usize::from_str_radix(n, 16).ok().and_then(|n| registers(cpu).get(n).copied()) {
+                Some(value) => word_hex(value),
+                None => "E01".to_string(),
+            },
+            ("P", assignment) => match set_register(cpu, assignment) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            ("m", args) => match parse_addr_len(args) {
+                Some((addr, len)) if len <= MAX_MEMORY_READ => (0..len)
+                    .map(|i| format!("{:02x}", cpu.memory().peek(addr.wrapping_add(i as u16))))
+                    .collect(),
+                _ => "E01".to_string(),
+            },
+            ("M", args) => match write_memory(cpu, args) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            ("s", addr) | ("c", addr) => {
+                if let Ok(addr) = u16::from_str_radix(addr, 16) {
+                    cpu.set_pc(addr);
+                }
+                if packet.starts_with('s') {
+                    debugger.single_step();
+                } else {
+                    debugger.resume();
+                }
+                return Ok(Some(Resume::Run));
+            }
+            ("Z", args) | ("z", args) => match point(args) {
+                Some(Point::Break(addr)) => {
+                    if packet.starts_with('Z') {
+                        debugger.add_breakpoint(addr);
+                    } else {
+                        debugger.remove_breakpoint(addr);
+                    }
+                    "OK".to_string()
+                }
+                Some(Point::Watch(watchpoint)) => {
+                    if packet.starts_with('Z') {
+                        debugger.add_watchpoint(watchpoint);
+                    } else {
+                        debugger.remove_watchpoint(&watchpoint);
+                    }
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            ("D", _) => {
+                let _ = self.send("OK");
+                self.stream = None;
+                debugger.detach();
+                return Ok(Some(Resume::Run));
+            }
+            ("k", _) => {
+                self.stream = None;
+                return Ok(Some(Resume::Quit));
+            }
+            ("H", _) => "OK".to_string(),
+            _ => query(packet),
+        };
+        if let Err(e) = self.send(&reply) {
+            self.stream = None;
+            debugger.detach();
+            return Err(e);
+        }
+        Ok(None)
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let Some(stream) = self.stream.as_mut() else {
+            return Ok(None);
+        };
+        let mut buf = [0; 256];
+        let n = stream.read(&mut buf)?;
+        self.pending.extend(&buf[..n]);
+        Ok(self.pending.pop_front())
+    }
+
+    /// Reads the next `$data#xx` packet, acknowledging it. `None` when the client disconnected.
+    fn receive(&mut self) -> io::Result<Option<String>> {
+        loop {
+            // acks, stray interrupts and noise between packets are skipped
+            loop {
+                match self.read_byte()? {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+            let (Some(high), Some(low)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let checksum = std::str::from_utf8(&[high, low]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok());
+            let stream = self.stream.as_mut().expect("connected while reading");
+            if checksum == Some(sum(&data)) {
+                stream.write_all(b"+")?;
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let stream = self.stream.as_mut().ok_or(io::ErrorKind::NotConnected)?;
+        write!(stream, "${}#{:02x}", data, sum(data.as_bytes()))?;
+        stream.flush()
+    }
+}
+
+enum Point {
+    Break(u16),
+    Watch(Watchpoint),
+}
+
+/// Parses the `type,addr,kind` arguments of Z and z packets.
+fn point(args: &str) -> Option<Point> {
+    let mut fields = args.split(',');
+    let kind = fields.next()?;
+    let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+    let len = u16::from_str_radix(fields.next()?.split(';').next()?, 16).ok()?;
+    let watch = match kind {
+        "0" | "1" => return Some(Point::Break(addr)),
+        "2" => Watch::Write,
+        "3" => Watch::Read,
+        "4" => Watch::ReadWrite,
+        _ => return None,
+    };
+    Some(Point::Watch(Watchpoint {
+        range: addr..=addr.saturating_add(len.max(1) - 1),
+        watch,
+        value: None,
+        log_only: false,
+    }))
+}
+
+fn stop_reply(watch_hit: Option<(Watch, u16)>) -> String {
+    match watch_hit {
+        Some((watch, addr)) => {
+            let kind = match watch {
+                Watch::Write => "watch",
+                Watch::Read => "rwatch",
+                Watch::ReadWrite => "awatch",
+            };
+            format!("T05{}:{:04x};", kind, addr)
+        }
+        None => "S05".to_string(),
+    }
+}
+
+/// Answers general queries, an empty reply tells the client a packet is not supported.
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        return format!("PacketSize={:x};qXfer:features:read+", MAX_MEMORY_READ * 2 + 16);
+    }
+    if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        let Some((offset, len)) = args
+            .split_once(',')
+            .and_then(|(offset, len)| Some((usize::from_str_radix(offset, 16).ok()?, usize::from_str_radix(len, 16).ok()?)))
+        else {
+            return "E01".to_string();
+        };
+        let offset = offset.min(TARGET_XML.len());
+        let end = (offset + len).min(TARGET_XML.len());
+        let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+        return format!("{}{}", more, &TARGET_XML[offset..end]);
+    }
+    match packet {
+        "qAttached" => "1".to_string(),
+        "qC" => "QC1".to_string(),
+        "qfThreadInfo" => "m1".to_string(),
+        "qsThreadInfo" => "l".to_string(),
+        _ => String::new(),
+    }
+}
+
+fn registers<M: Bus, I: IoBus>(cpu: &Cpu8080<M, I>) -> [u16; 6] {
+    [cpu.psw(), cpu.bc(), cpu.de(), cpu.hl(), cpu.sp(), cpu.pc()]
+}
+
+fn set_registers<M: Bus, I: IoBus>(cpu: &mut Cpu8080<M, I>, words: &[u16]) {
+    for (n, value) in words.iter().enumerate() {
+        set_register_n(cpu, n, *value);
+    }
+}
+
+fn set_register_n<M: Bus, I: IoBus>(cpu: &mut Cpu8080<M, I>, n: usize, value: u16) -> Option<()> {
+    match n {
+        0 => cpu.set_psw(value),
+        1 => cpu.set_bc(value),
+        2 => cpu.set_de(value),
+        3 => cpu.set_hl(value),
+        4 => cpu.set_sp(value),
+        5 => cpu.set_pc(value),
+        _ => return None,
+    }
+    Some(())
+}
+
+/// `P n=value`
+fn set_register<M: Bus, I: IoBus>(cpu: &mut Cpu8080<M, I>, assignment: &str) -> Option<()> {
+    let (n, value) = assignment.split_once('=')?;
+    let n = usize::from_str_radix(n, 16).ok()?;
+    let value = *parse_words(value)?.first()?;
+    set_register_n(cpu, n, value)
+}
+
+/// `M addr,len:bytes`
+fn write_memory<M: Bus, I: IoBus>(cpu: &mut Cpu8080<M, I>, args: &str) -> Option<()> {
+    let (target, hex) = args.split_once(':')?;
+    let (addr, len) = parse_addr_len(target)?;
+    let bytes = parse_bytes(hex)?;
+    if bytes.len() != len {
+        return None;
+    }
+    for (i, byte) in bytes.into_iter().enumerate() {
+        cpu.memory_mut().write(addr.wrapping_add(i as u16), byte);
+    }
+    Some(())
+}
+
+fn parse_addr_len(args: &str) -> Option<(u16, usize)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((u16::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn parse_bytes(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Little endian 16 bit words as GDB sends them.
+fn parse_words(hex: &str) -> Option<Vec<u16>> {
+    let bytes = parse_bytes(hex)?;
+    if !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(bytes.chunks(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect())
+}
+
+fn word_hex(value: u16) -> String {
+    let [low, high] = value.to_le_bytes();
+    format!("{:02x}{:02x}", low, high)
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
@@ -0,0 +1,172 @@
+use minifb::{Key, Window};
+use rust_8080::invaders::{Button, DipSwitches, InvadersIo};
+use std::fs;
+use std::path::Path;
+
+/// Keys minifb can report that may be bound to a button.
+const KEYS: [Key; 52] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I,
+    Key::J, Key::K, Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R,
+    Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Key0, Key::Key1, Key::Key2, Key::Key3, Key::Key4,
+    Key::Key5, Key::Key6, Key::Key7, Key::Key8, Key::Key9,
+    Key::Left, Key::Right, Key::Up, Key::Down,
+    Key::Space, Key::Enter, Key::Tab, Key::Backspace,
+    Key::LeftShift, Key::RightShift, Key::LeftCtrl, Key::RightCtrl,
+    Key::LeftAlt, Key::RightAlt, Key::Comma, Key::Period,
+];
+
+/// Keyboard bindings for the cabinet controls.
+pub struct KeyMap {
+    bindings: Vec<(Button, Key)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        KeyMap {
+            bindings: vec![
+                (Button::Coin, Key::C),
+                (Button::Start1, Key::Key1),
+                (Button::Start2, Key::Key2),
+                (Button::Fire1, Key::Space),
+                (Button::Left1, Key::Left),
+                (Button::Right1, Key::Right),
+                (Button::Fire2, Key::W),
+                (Button::Left2, Key::A),
+                (Button::Right2, Key::D),
+                (Button::Tilt, Key::T),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    pub fn bind(&mut self, button: Button, key: Key) {
+        self.bindings.retain(|(b, _)| *b != button);
+        self.bindings.push((button, key));
+    }
+
+    /// Copies the state of every bound key into the input ports.
+    pub fn poll(&self, window: &Window, io: &mut InvadersIo) {
+        for (button, key) in &self.bindings {
+            io.set_button(*button, window.is_key_down(*key));
+        }
+    }
+}
+
+/// Reads a config file of `name = value` lines, binding buttons to keys
+/// (`fire1 = Space`) and setting the DIP switches (`lives = 5`,
+/// `bonus_life = 1000`, `coin_info = off`). `#` starts a comment.
+pub fn load_config(path: &Path, keymap: &mut KeyMap, dips: &mut DipSwitches) -> Result<(), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let path = path.display();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .split_once('=')
+            .map(|(name, value)| (name.trim().to_lowercase(), value.trim()))
+            .ok_or_else(|| format!("{}:{}: expected name = value", path, n + 1))?;
+        let error = |what: &str| format!("{}:{}: {} '{}'", path, n + 1, what, value);
+        match name.as_str() {
+            "lives" => match value.parse::<u8>() {
+                Ok(lives @ 3..=6) => dips.lives = lives,
+                _ => return Err(error("lives must be 3 to 6, got")),
+            },
+            "bonus_life" => match value {
+                "1000" => dips.bonus_life_at_1000 = true,
+                "1500" => dips.bonus_life_at_1000 = false,
+                _ => return Err(error("bonus_life must be 1000 or 1500, got")),
+            },
+            "coin_info" => match value {
+                "on" => dips.coin_info = true,
+                "off" => dips.coin_info = false,
+                _ => return Err(error("coin_info must be on or off, got")),
+            },
+            _ => {
+                let button = Button::ALL
+                    .into_iter()
+                    .find(|b| format!("{:?}", b).to_lowercase() == name)
+                    .ok_or_else(|| format!("{}:{}: unknown setting '{}'", path, n + 1, name))?;
+                let key = parse_key(value).ok_or_else(|| error("unknown key"))?;
+                keymap.bind(button, key);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_key(name: &str) -> Option<Key> {
+    KEYS.into_iter().find(|key| {
+        let debug = format!("{:?}", key);
+        debug.eq_ignore_ascii_case(name) || debug.strip_prefix("Key") == Some(name)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A config file of its own for every test.
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rust_8080-{}-{}.cfg", name, std::process::id()))
+    }
+
+    /// Loads `text` as a config file on top of the defaults.
+    fn load(name: &str, text: &str) -> Result<(KeyMap, DipSwitches), String> {
+        let path = path(name);
+        fs::write(&path, text).unwrap();
+        let (mut keymap, mut dips) = (KeyMap::default(), DipSwitches::default());
+        let result = load_config(&path, &mut keymap, &mut dips);
+        fs::remove_file(&path).unwrap();
+        result.map(|()| (keymap, dips))
+    }
+
+    #[test]
+    fn bindings_and_switches() {
+        let (keymap, dips) = load(
+            "config",
+            "# player 1\nfire1 = LeftCtrl\nLEFT1=z # lower case\n\nstart1 = 5\nlives = 6\nbonus_life = 1000\ncoin_info = off\n",
+        )
+        .unwrap();
+        let key = |button| keymap.bindings.iter().find(|(b, _)| *b == button).map(|(_, key)| *key);
+        assert_eq!(key(Button::Fire1), Some(Key::LeftCtrl));
+        assert_eq!(key(Button::Left1), Some(Key::Z));
+        assert_eq!(key(Button::Start1), Some(Key::Key5));
+        assert_eq!(key(Button::Coin), Some(Key::C));
+        assert_eq!(keymap.bindings.len(), Button::ALL.len());
+        assert_eq!(
+            dips,
+            DipSwitches {
+                lives: 6,
+                bonus_life_at_1000: true,
+                coin_info: false,
+            }
+        );
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let file = path("errors").display().to_string();
+        let error = |text| load("errors", text).err().expect("the config is invalid");
+        assert_eq!(error("jump = Space"), format!("{}:1: unknown setting 'jump'", file));
+        assert_eq!(error("\nfire1 = Hyper"), format!("{}:2: unknown key 'Hyper'", file));
+        assert_eq!(error("fire1 Space"), format!("{}:1: expected name = value", file));
+        assert_eq!(error("lives = 7"), format!("{}:1: lives must be 3 to 6, got '7'", file));
+        assert_eq!(error("bonus_life = 2000"), format!("{}:1: bonus_life must be 1000 or 1500, got '2000'", file));
+        assert_eq!(error("coin_info = yes"), format!("{}:1: coin_info must be on or off, got 'yes'", file));
+        assert!(load_config(&path("missing"), &mut KeyMap::default(), &mut DipSwitches::default()).is_err());
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(parse_key("space"), Some(Key::Space));
+        assert_eq!(parse_key("Key1"), Some(Key::Key1));
+        assert_eq!(parse_key("1"), Some(Key::Key1));
+        assert_eq!(parse_key("F1"), None);
+        assert_eq!(parse_key(""), None);
+    }
+}
@@ -0,0 +1,278 @@
+use crate::io::IoBus;
+use crate::savestate::{Input, Snapshot, StateError};
+use crate::sound::{AudioSink, SoundLatches};
+use crate::timing::Scheduler;
+
+/// The cabinet's 19.968 MHz crystal divided by 10.
+pub const CPU_CLOCK_HZ: u32 = 1_996_800;
+pub const FRAMES_PER_SECOND: u32 = 60;
+pub const CYCLES_PER_FRAME: u32 = CPU_CLOCK_HZ / FRAMES_PER_SECOND;
+/// Scanlines per frame including vertical blanking.
+pub const SCANLINES: u32 = 262;
+/// RST 1 fires when the beam reaches this line, RST 2 at the start of vertical blanking.
+pub const MID_SCREEN_LINE: u32 = 96;
+pub const END_OF_SCREEN_LINE: u32 = 224;
+
+/// Cycle within the frame at which the beam starts drawing `line`.
+pub fn scanline_cycle(line: u32) -> u32 {
+    line * CYCLES_PER_FRAME / SCANLINES
+}
+
+/// Frame timing of the cabinet: RST 1 at mid-screen and RST 2 at the end of the screen.
+pub fn scheduler() -> Scheduler {
+    Scheduler::new(
+        CYCLES_PER_FRAME,
+        vec![
+            (scanline_cycle(MID_SCREEN_LINE), 1),
+            (scanline_cycle(END_OF_SCREEN_LINE), 2),
+        ],
+    )
+}
+
+/// Space Invaders shift register on ports 2 (offset), 3 (result) and 4 (data).
+pub struct ShiftRegister {
+    shift_offset: u8,
+    shift0: u8,
+    shift1: u8,
+}
+
+impl Default for ShiftRegister {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShiftRegister {
+    pub fn new() -> Self {
+        ShiftRegister {
+            shift_offset: 0,
+            shift0: 0,
+            shift1: 0,
+        }
+    }
+
+    pub fn result(&self) -> u8 {
+        let v: u16 = (u16::from(self.shift1) << 8) | u16::from(self.shift0);
+        let buffer: u16 = (v >> (8 - self.shift_offset)) & 0xff;
+        buffer.to_be_bytes()[1]
+    }
+}
+
+impl IoBus for ShiftRegister {
+    fn input(&mut self, port: u8) -> u8 {
+        match port {
+            3 => self.result(),
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 => {
+                self.shift_offset = value & 0x7;
+            }
+            4 => {
+                self.shift0 = self.shift1;
+                self.shift1 = value;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Cabinet controls wired to input ports 1 and 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Button {
+    Coin,
+    Start1,
+    Start2,
+    Fire1,
+    Left1,
+    Right1,
+    Fire2,
+    Left2,
+    Right2,
+    Tilt,
+}
+
+impl Button {
+    pub const ALL: [Button; 10] = [
+        Button::Coin,
+        Button::Start1,
+        Button::Start2,
+        Button::Fire1,
+        Button::Left1,
+        Button::Right1,
+        Button::Fire2,
+        Button::Left2,
+        Button::Right2,
+        Button::Tilt,
+    ];
+
+    /// Port and bit the button is wired to.
+    fn wiring(&self) -> (u8, u8) {
+        match self {
+            Button::Coin => (1, 0),
+            Button::Start2 => (1, 1),
+            Button::Start1 => (1, 2),
+            Button::Fire1 => (1, 4),
+            Button::Left1 => (1, 5),
+            Button::Right1 => (1, 6),
+            Button::Tilt => (2, 2),
+            Button::Fire2 => (2, 4),
+            Button::Left2 => (2, 5),
+            Button::Right2 => (2, 6),
+        }
+    }
+}
+
+/// DIP switch bank read through port 2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DipSwitches {
+    /// Ships per game, 3 to 6.
+    pub lives: u8,
+    /// Extra ship at 1000 points instead of 1500.
+    pub bonus_life_at_1000: bool,
+    /// Show the coin info in the demo screen.
+    pub coin_info: bool,
+}
+
+impl Default for DipSwitches {
+    fn default() -> Self {
+        DipSwitches {
+            lives: 3,
+            bonus_life_at_1000: false,
+            coin_info: true,
+        }
+    }
+}
+
+impl DipSwitches {
+    fn port2_bits(&self) -> u8 {
+        let mut bits = self.lives.clamp(3, 6) - 3;
+        if self.bonus_life_at_1000 {
+            bits |= 0b1000;
+        }
+        if !self.coin_info {
+            bits |= 0b10000000;
+        }
+        bits
+    }
+}
+
+/// All Space Invaders I/O: input ports 0-2, the shift register on ports 2-4,
+/// sound latches on ports 3 and 5 and the watchdog on port 6.
+pub struct InvadersIo {
+    shift_register: ShiftRegister,
+    ports: [u8; 3],
+    sound: SoundLatches,
+    pub dip_switches: DipSwitches,
+}
+
+impl Default for InvadersIo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl InvadersIo {
+    pub fn new() -> Self {
+        InvadersIo {
+            shift_register: ShiftRegister::new(),
+            ports: [0; 3],
+            sound: SoundLatches::default(),
+            dip_switches: DipSwitches::default(),
+        }
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let (port, bit) = button.wiring();
+        if pressed {
+            self.ports[usize::from(port)] |= 1 << bit;
+        } else {
+            self.ports[usize::from(port)] &= !(1 << bit);
+        }
+    }
+
+    /// Button bits of input ports 1 and 2, without the fixed and DIP switch bits.
+    pub fn buttons(&self) -> [u8; 2] {
+        [self.ports[1], self.ports[2]]
+    }
+
+    pub fn set_buttons(&mut self, buttons: [u8; 2]) {
+        [self.ports[1], self.ports[2]] = buttons;
+    }
+
+    pub fn set_audio_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sound.set_sink(sink);
+    }
+
+    /// Value the CPU reads from input port 0, 1 or 2.
+    pub fn port(&self, port: u8) -> u8 {
+        match port {
+            // bits 1-3 are tied high, 4-6 mirror the player 1 controls
+            0 => 0b1110 | (self.ports[1] & 0b1110000),
+            // bit 3 is tied high
+            1 => self.ports[1] | 0b1000,
+            2 => self.ports[2] | self.dip_switches.port2_bits(),
+            _ => 0,
+        }
+    }
+}
+
+impl IoBus for InvadersIo {
+    fn input(&mut self, port: u8) -> u8 {
+        match port {
+            0..=2 => self.port(port),
+            3 => self.shift_register.input(port),
+            _ => 0,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            2 | 4 => self.shift_register.output(port, value),
+            3 | 5 => self.sound.write(port, value),
+            // watchdog
+            _ => {}
+        }
+    }
+}
+
+/// Shift register, input ports, sound latches and DIP switches.
+impl Snapshot for InvadersIo {
+    fn save(&self, out: &mut Vec<u8>) {
+        let shift = &self.shift_register;
+        out.extend_from_slice(&[shift.shift_offset, shift.shift0, shift.shift1]);
+        out.extend_from_slice(&self.ports);
+        let (port3, port5) = self.sound.latches();
+        out.extend_from_slice(&[port3, port5]);
+        let dips = &self.dip_switches;
+        out.extend_from_slice(&[dips.lives, u8::from(dips.bonus_life_at_1000), u8::from(dips.coin_info)]);
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        let [shift_offset, shift0, shift1] = input.bytes(3)?.try_into().unwrap();
+        if shift_offset > 7 {
+            return Err(StateError::Corrupt);
+        }
+        self.shift_register = ShiftRegister {
+            shift_offset,
+            shift0,
+            shift1,
+        };
+        self.ports = input.bytes(3)?.try_into().unwrap();
+        let (port3, port5) = (input.u8()?, input.u8()?);
+        self.sound.restore_latches(port3, port5);
+        let lives = input.u8()?;
+        if !(3..=6).contains(&lives) {
+            return Err(StateError::Corrupt);
+        }
+        self.dip_switches = DipSwitches {
+            lives,
+            bonus_life_at_1000: input.bool()?,
+            coin_info: input.bool()?,
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use std::ops::RangeInclusive;
+
+/// A device reachable through the 8080 IN and OUT instructions.
+pub trait IoBus {
+    fn input(&mut self, port: u8) -> u8;
+
+    fn output(&mut self, port: u8, value: u8);
+
+    /// An error of the last IN or OUT, which the CPU reports and stops on.
+    fn take_fault(&mut self) -> Option<String> {
+        None
+    }
+}
+
+impl<T: IoBus + ?Sized> IoBus for Box<T> {
+    fn input(&mut self, port: u8) -> u8 {
+        (**self).input(port)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        (**self).output(port, value)
+    }
+
+    fn take_fault(&mut self) -> Option<String> {
+        (**self).take_fault()
+    }
+}
+
+/// Routes IN/OUT to the device attached to the port range. Ports nobody claims read
+/// as the unmapped value and ignore writes.
+pub struct IoMap {
+    devices: Vec<(RangeInclusive<u8>, Box<dyn IoBus>)>,
+    unmapped_value: u8,
+}
+
+impl Default for IoMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoMap {
+    pub fn new() -> Self {
+        IoMap {
+            devices: Vec::new(),
+            unmapped_value: 0,
+        }
+    }
+
+    /// Attaches `device` to `ports`. Devices see absolute port numbers; when ranges
+    /// overlap the device attached first wins.
+    pub fn attach(&mut self, ports: RangeInclusive<u8>, device: Box<dyn IoBus>) {
+        self.devices.push((ports, device));
+    }
+
+    pub fn set_unmapped_value(&mut self, value: u8) {
+        self.unmapped_value = value;
+    }
+
+    fn device(&mut self, port: u8) -> Option<&mut Box<dyn IoBus>> {
+        self.devices
+            .iter_mut()
+            .find(|(ports, _)| ports.contains(&port))
+            .map(|(_, device)| device)
+    }
+}
+
+impl IoBus for IoMap {
+    fn input(&mut self, port: u8) -> u8 {
+        let unmapped_value = self.unmapped_value;
+        match self.device(port) {
+            Some(device) => device.input(port),
+            None => unmapped_value,
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        if let Some(device) = self.device(port) {
+            device.output(port, value);
+        }
+    }
+
+    fn take_fault(&mut self) -> Option<String> {
+        self.devices.iter_mut().find_map(|(_, device)| device.take_fault())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Log = Rc<RefCell<Vec<(&'static str, u8, Option<u8>)>>>;
+
+    /// Logs `(name, port, value written)` and reads as `id`. Writing `$ff` faults.
+    struct Recording {
+        name: &'static str,
+        id: u8,
+        log: Log,
+        fault: Option<String>,
+    }
+
+    fn device(name: &'static str, id: u8, log: &Log) -> Box<Recording> {
+        Box::new(Recording {
+            name,
+            id,
+            log: log.clone(),
+            fault: None,
+        })
+    }
+
+    impl IoBus for Recording {
+        fn input(&mut self, port: u8) -> u8 {
+            self.log.borrow_mut().push((self.name, port, None));
+            self.id
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.log.borrow_mut().push((self.name, port, Some(value)));
+            if value == 0xff {
+                self.fault = Some(format!("{} refused {:02x}", self.name, value));
+            }
+        }
+
+        fn take_fault(&mut self) -> Option<String> {
+            self.fault.take()
+        }
+    }
+
+    #[test]
+    fn routes_by_port_first_device_wins() {
+        let log = Log::default();
+        let mut io = IoMap::new();
+        io.attach(0x10..=0x1f, device("low", 1, &log));
+        io.attach(0x18..=0x2f, device("high", 2, &log));
+        io.set_unmapped_value(0xee);
+
+        assert_eq!([io.input(0x10), io.input(0x1f), io.input(0x20), io.input(0x2f)], [1, 1, 2, 2]);
+        // 0x18-0x1f overlap and stay with the device attached first
+        assert_eq!(io.input(0x18), 1);
+        assert_eq!((io.input(0x0f), io.input(0x30)), (0xee, 0xee));
+        io.output(0x1c, 0x42);
+        io.output(0x2a, 0x43);
+        io.output(0x80, 0x44);
+        assert_eq!(
+            log.borrow().as_slice(),
+            [
+                ("low", 0x10, None),
+                ("low", 0x1f, None),
+                ("high", 0x20, None),
+                ("high", 0x2f, None),
+                ("low", 0x18, None),
+                ("low", 0x1c, Some(0x42)),
+                ("high", 0x2a, Some(0x43)),
+            ]
+        );
+        assert_eq!(IoMap::default().input(0x10), 0);
+    }
+
+    #[test]
+    fn faults_of_any_device_are_taken_once() {
+        let log = Log::default();
+        let mut io = IoMap::new();
+        io.attach(0x00..=0x0f, device("low", 1, &log));
+        io.attach(0x10..=0x1f, device("high", 2, &log));
+        assert_eq!(io.take_fault(), None);
+        io.output(0x12, 0xff);
+        assert_eq!(io.take_fault().as_deref(), Some("high refused ff"));
+        assert_eq!(io.take_fault(), None);
+
+        // through a box, as the CPU holds it
+        let mut boxed: Box<dyn IoBus> = Box::new(io);
+        boxed.output(0x03, 0xff);
+        assert_eq!(boxed.take_fault().as_deref(), Some("low refused ff"));
+        assert_eq!(boxed.take_fault(), None);
+    }
+}
@@ -0,0 +1,29 @@
+pub mod cpm;
+pub mod cpu;
+pub mod debugger;
+pub mod disasm;
+pub mod gdb;
+pub mod invaders;
+pub mod io;
+pub mod lockstep;
+pub mod memory;
+pub mod movie;
+pub mod report;
+pub mod rewind;
+pub mod rom;
+pub mod screen;
+pub mod savestate;
+pub mod singlestep;
+pub mod sound;
+pub mod timing;
+pub mod trace;
+pub mod watch;
+
+pub use crate::cpu::debugging::Instructioninfo;
+pub use crate::cpu::instructions;
+pub use crate::cpu::Cpu8080;
+pub use crate::cpu::EmulationError;
+pub use crate::cpu::ConditionCodes;
+pub use crate::cpu::State8080;
+pub use crate::memory::{Bus, MemoryMap};
+pub use crate::io::{IoBus, IoMap};
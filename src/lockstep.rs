@@ -0,0 +1,249 @@
+use crate::cpu::Cpu8080;
+use crate::disasm;
+use crate::io::IoBus;
+use crate::memory::Bus;
+use crate::singlestep::Mismatch;
+use std::fmt;
+use std::io::{self, BufRead};
+
+/// Machine state before one instruction of a reference log. Fields the log does
+/// not have are not compared.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Expected {
+    pub pc: Option<u16>,
+    pub sp: Option<u16>,
+    pub a: Option<u8>,
+    pub f: Option<u8>,
+    pub b: Option<u8>,
+    pub c: Option<u8>,
+    pub d: Option<u8>,
+    pub e: Option<u8>,
+    pub h: Option<u8>,
+    pub l: Option<u8>,
+    /// T-states elapsed before the instruction.
+    pub cycles: Option<u64>,
+}
+
+impl Expected {
+    /// Parses one line of a reference log, `None` for blank lines and `#` comments.
+    ///
+    /// A line holds `NAME: VALUE` or `NAME=VALUE` fields separated by commas or
+    /// spaces, in any order, such as
+    /// `PC: 0100, AF: 0002, BC: 0000, DE: 0000, HL: 0000, SP: f000, CYC: 7`.
+    /// Names are PC, SP, AF (or PSW), A, F, BC, DE, HL, B, C, D, E, H, L and CYC,
+    /// values are hex except CYC. F is a byte or five of `SZAPC` with `-` for a
+    /// clear flag. Other fields are ignored. Lines of a `--trace` text trace are
+    /// read as well.
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let mut expected = Expected::default();
+        let mut tokens = line.split([',', ' ', '\t']).filter(|token| !token.is_empty());
+        // instruction number, cycles and PC of a text trace
+        let words: Vec<&str> = line.split_whitespace().take(3).collect();
+        if let [n, cycles, pc] = words[..] {
+            if n.parse::<u64>().is_ok() && pc.len() == 4 {
+                if let (Ok(cycles), Ok(pc)) = (cycles.parse(), u16::from_str_radix(pc, 16)) {
+                    expected.cycles = Some(cycles);
+                    expected.pc = Some(pc);
+                }
+            }
+        }
+        let mut found = expected.pc.is_some();
+        while let Some(token) = tokens.next() {
+            let (name, value) = match token.split_once([':', '=']) {
+                Some((name, "")) => match tokens.next() {
+                    Some(value) => (name, value),
+                    None => break,
+                },
+                Some((name, value)) => (name, value),
+                None => continue,
+            };
+            let name = name.to_ascii_uppercase();
+            let error = || format!("bad value '{}' for {}", value, name);
+            let byte = || hex(value).and_then(|v| u8::try_from(v).ok()).ok_or_else(error);
+            let word = || hex(value).ok_or_else(error);
+            match name.as_str() {
+                "PC" => expected.pc = Some(word()?),
+                "SP" => expected.sp = Some(word()?),
+                "AF" | "PSW" => [expected.a, expected.f] = word()?.to_be_bytes().map(Some),
+                "BC" => [expected.b, expected.c] = word()?.to_be_bytes().map(Some),
+                "DE" => [expected.d, expected.e] = word()?.to_be_bytes().map(Some),
+                "HL" => [expected.h, expected.l] = word()?.to_be_bytes().map(Some),
+                "A" => expected.a = Some(byte()?),
+                "F" => expected.f = Some(flag_letters(value).map_or_else(byte, Ok)?),
+                "B" => expected.b = Some(byte()?),
+                "C" => expected.c = Some(byte()?),
+                "D" => expected.d = Some(byte()?),
+                "E" => expected.e = Some(byte()?),
+                "H" => expected.h = Some(byte()?),
+                "L" => expected.l = Some(byte()?),
+                "CYC" | "CYCLES" => expected.cycles = Some(value.parse().map_err(|_| error())?),
+                _ => continue,
+            }
+            found = true;
+        }
+        if !found {
+            return Err("no registers found".to_string());
+        }
+        Ok(Some(expected))
+    }
+
+    /// The fields that differ from the CPU, which has executed `cycles` T-states.
+    pub fn compare<M: Bus, I: IoBus>(&self, cpu: &Cpu8080<M, I>, cycles: u64) -> Vec<Mismatch> {
+        let mut mismatches = Vec::new();
+        let mut check = |field: &str, expected: Option<String>, found: String| {
+            if let Some(expected) = expected.filter(|expected| *expected != found) {
+                mismatches.push(Mismatch {
+                    field: field.to_string(),
+                    expected,
+                    found,
+                });
+            }
+        };
+        let word = |value: Option<u16>| value.map(|value| format!("{:04x}", value));
+        let byte = |value: Option<u8>| value.map(|value| format!("{:02x}", value));
+        check("pc", word(self.pc), format!("{:04x}", cpu.pc()));
+        check("sp", word(self.sp), format!("{:04x}", cpu.sp()));
+        for (name, expected, found) in [
+            ("a", self.a, cpu.a()),
+            ("b", self.b, cpu.b()),
+            ("c", self.c, cpu.c()),
+            ("d", self.d, cpu.d()),
+            ("e", self.e, cpu.e()),
+            ("h", self.h, cpu.h()),
+            ("l", self.l, cpu.l()),
+        ] {
+            check(name, byte(expected), format!("{:02x}", found));
+        }
+        // only S Z AC P CY, the fixed bits differ between emulators
+        let flags = |f: u8| {
+            let letters: String = [(0x80, 'S'), (0x40, 'Z'), (0x10, 'A'), (0x04, 'P'), (0x01, 'C')]
+                .iter()
+                .map(|(bit, name)| if f & bit != 0 { *name } else { '-' })
+                .collect();
+            letters
+        };
+        check("f", self.f.map(flags), flags(cpu.flags().psw()));
+        check("cycles", self.cycles.map(|cycles| cycles.to_string()), cycles.to_string());
+        mismatches
+    }
+}
+
+fn hex(text: &str) -> Option<u16> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix('$')).unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+/// `SZAPC` with `-` for clear flags, as a flags byte.
+fn flag_letters(text: &str) -> Option<u8> {
+    let bits = [(b'S', 0x80), (b'Z', 0x40), (b'A', 0x10), (b'P', 0x04), (b'C', 0x01)];
+    if text.len() != bits.len() {
+        return None;
+    }
+    text.bytes().zip(bits).try_fold(0x02, |f, (letter, (name, bit))| match letter {
+        b'-' => Some(f),
+        _ if letter == name => Some(f | bit),
+        _ => None,
+    })
+}
+
+/// The first instruction at which the core left the reference.
+#[derive(Clone, Debug)]
+pub struct Divergence {
+    /// Instructions both agreed on.
+    pub instruction: u64,
+    /// Line of the reference log.
+    pub line: usize,
+    /// The instruction about to execute.
+    pub location: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "first mismatch before instruction {} (reference line {}):",
+            self.instruction, self.line
+        )?;
+        writeln!(f, "  {}", self.location)?;
+        for mismatch in &self.mismatches {
+            writeln!(f, "  {}", mismatch)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum LockstepError {
+    Io(io::Error),
+    Parse { line: usize, message: String },
+    Diverged(Divergence),
+}
+
+impl fmt::Display for LockstepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockstepError::Io(error) => write!(f, "reading the reference log: {}", error),
+            LockstepError::Parse { line, message } => write!(f, "reference line {}: {}", line, message),
+            LockstepError::Diverged(divergence) => write!(f, "{}", divergence),
+        }
+    }
+}
+
+impl std::error::Error for LockstepError {}
+
+/// Checks the CPU against a reference log before every instruction.
+pub struct Lockstep<R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+    instructions: u64,
+}
+
+impl<R: BufRead> Lockstep<R> {
+    pub fn new(reference: R) -> Self {
+        Lockstep {
+            lines: reference.lines(),
+            line: 0,
+            instructions: 0,
+        }
+    }
+
+    /// Compares the CPU with the next entry of the log. Call once before every
+    /// step. `Ok(false)` once the log has ended.
+    pub fn check<M: Bus, I: IoBus>(&mut self, cpu: &Cpu8080<M, I>, cycles: u64) -> Result<bool, LockstepError> {
+        let expected = loop {
+            let Some(line) = self.lines.next() else {
+                return Ok(false);
+            };
+            self.line += 1;
+            let line = line.map_err(LockstepError::Io)?;
+            let parsed = Expected::parse(&line).map_err(|message| LockstepError::Parse {
+                line: self.line,
+                message,
+            })?;
+            if let Some(expected) = parsed {
+                break expected;
+            }
+        };
+        let mismatches = expected.compare(cpu, cycles);
+        if !mismatches.is_empty() {
+            return Err(LockstepError::Diverged(Divergence {
+                instruction: self.instructions,
+                line: self.line,
+                location: disasm::disassemble(cpu.memory(), cpu.pc()).to_string(),
+                mismatches,
+            }));
+        }
+        self.instructions += 1;
+        Ok(true)
+    }
+
+    /// Instructions checked so far.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+}
@@ -1,1134 +1,675 @@
 //#[allow(dead_code)]
 //mod render;
-mod cpu;
-
-pub use crate::cpu::debugging::Instructioninfo;
-pub use crate::cpu::instructions;
-pub use crate::cpu::state8080::ConditionCodes;
-pub use crate::cpu::state8080::State8080;
-
-use minifb::{Window, /*ScaleMode,*/ WindowOptions};
-//use std::io;
-use std::fs::File;
-use std::io::Read;
+mod cli;
+mod input;
+
+use cli::{Machine, Options};
+use input::KeyMap;
+use rust_8080::cpm;
+use rust_8080::debugger::{self, Debugger, Resume};
+use rust_8080::gdb::GdbStub;
+use rust_8080::invaders::{self, InvadersIo};
+use rust_8080::lockstep::{Lockstep, LockstepError};
+use rust_8080::movie::{self, Movie, Start};
+use rust_8080::rom::{self, Rom, RomError};
+use rust_8080::savestate::{self, Snapshot};
+use rust_8080::screen;
+use rust_8080::sound::{AudioSink, NullSink};
+use rust_8080::timing::{Pacer, Scheduler};
+use rust_8080::trace::Tracer;
+use rust_8080::watch::Recorder;
+use rust_8080::report::Reporter;
+use rust_8080::rewind::Rewind;
+use rust_8080::{Bus, Cpu8080, EmulationError, Instructioninfo, IoBus, MemoryMap};
+
+use minifb::{Key, KeyRepeat, Scale, Window, WindowOptions};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 use std::process;
-use std::process::exit;
-use std::{thread, time};
 
-const SCREEN_WIDTH: usize = 224;
-const SCREEN_HEIGHT: usize = 256;
-const NUM_PIXELS: usize = SCREEN_HEIGHT * SCREEN_WIDTH;
+/// Key bindings and DIP switch settings used when `--config` is not given, see `input::load_config`.
+const CONFIG_FILE: &str = "invaders.cfg";
+/// Writes a report of the machine state without stopping.
+const DUMP_KEY: Key = Key::F12;
+/// Save state hotkeys: save to and load from the selected slot, select the previous
+/// or next slot.
+const SAVE_STATE_KEY: Key = Key::F5;
+const LOAD_STATE_KEY: Key = Key::F9;
+const PREV_SLOT_KEY: Key = Key::F6;
+const NEXT_SLOT_KEY: Key = Key::F7;
+const STATE_SLOTS: u8 = 10;
+/// Steps back one frame per frame while held.
+const REWIND_KEY: Key = Key::F8;
+/// Saves the screen as PNG.
+const CAPTURE_KEY: Key = Key::F10;
 
 fn main() {
-    /** 2MHz/1Mhz with two interrupts for each frame on 60Hz screen */
-    static CYCLES_PER_FRAME: isize = 1_000_000 / 60;
-
-    let condition: ConditionCodes = ConditionCodes {
-        z: false,
-        s: false,
-        p: false,
-        cy: false,
-        ac: false,
-        pad: 0,
-    };
-    let mut state: State8080 = State8080 {
-        a: 0,
-        b: 0,
-        c: 0,
-        d: 0,
-        e: 0,
-        h: 0,
-        l: 0,
-        sp: 0xF000,
-        pc: 0,
-        memory: [0; 16384],
-        cc: condition,
-        int_enable: false,
-    };
-    let mut special: Special = Special {
-        shift_offset: 0,
-        shift0: 0,
-        shift1: 0,
-    };
-
-    //load rom to memory
-    let mut invadersh: File = File::open("invaders.h").expect("no such file");
-    invadersh
-        .read(&mut state.memory[..=0x07ff])
-        .expect("error reading into emulated memory");
-
-    let mut invadersg = File::open("invaders.g").expect("no such file");
-    invadersg
-        .read(&mut state.memory[0x0800..=0x0fff])
-        .expect("error reading into emulated memory");
-
-    let mut invadersf = File::open("invaders.f").expect("no such file");
-    invadersf
-        .read(&mut state.memory[0x1000..=0x17ff])
-        .expect("error reading into emulated memory");
-
-    let mut invaderse = File::open("invaders.e").expect("no such file");
-    invaderse
-        .read(&mut state.memory[0x1800..=0x1fff])
-        .expect("error reading into emulated memory");
-
-    // minifb window
-    let mut window = Window::new(
-        "8080",
-        SCREEN_WIDTH,
-        SCREEN_HEIGHT,
-        WindowOptions::default(),
-    )
-    .unwrap_or_else(|e| {
-        panic!("{}", e);
+    let options = cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        process::exit(2);
     });
-
-    window.limit_update_rate(Some(std::time::Duration::from_millis(4)));
-
-    let mut cycles: isize = 0;
-    let mut interrupt_type: bool = false;
-    let mut total_instructions: usize = 0;
-
-    //main emulation loop
-    loop {
-        // while no interrupt
-        while cycles <= CYCLES_PER_FRAME / 2 {
-
-            // do emulation step (cpu instruction)
-            if !emulate_instruction(
-                &mut state,
-                &mut cycles,
-                &mut special,
-            ) {
-                //exit if emulate_instruction returns false (-> error)
-                println!("Emulation aborted due to an error");
-                dump_memory(&state, &total_instructions, &0, &state.pc, &cycles);
-                process::exit(0x0);
-            }
-            total_instructions += 1;
-        }
-
-        // render pixels from emulated ram in minifb window
-        let mut buffer: Vec<u32> = vec![0; NUM_PIXELS];
-        let mut j = 0;
-        for row in (0x2400..=0x241f).rev() {
-            for b in (0..=7).rev() {
-                for col in 0..224 {
-                    let offset = row + (col * 0x20);
-                    if (state.memory[offset] & (0x1 << b)) != 0x0 {
-                        buffer[j] = 0x00ffffff;
-                    } else {
-                        buffer[j] = 0x00000000;
-                    }
-                    j += 1;
-                }
-            }
-        }
-
-        window
-            .update_with_buffer(&buffer, SCREEN_WIDTH, SCREEN_HEIGHT)
-            .unwrap_or_else(|e| {
-                panic!("{}", e);
-            });
-
-        // process interrupt
-        if state.int_enable {
-            generate_interrupt(&mut state, &mut interrupt_type);
-            interrupt_type = !interrupt_type;
-        }
-        cycles = cycles - (CYCLES_PER_FRAME / 2);
-
-
-        if total_instructions > 50000000 {
-            println!("total_instructions ({:?}) > 50000000, exiting...", total_instructions);
-            thread::sleep(time::Duration::from_secs(10));
-            break;
-        }
-        thread::sleep(time::Duration::from_millis(4));
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    match options.machine {
+        Machine::SpaceInvaders => run_invaders(&options),
+        Machine::Cpm | Machine::Bare => run_program(&options),
     }
 }
 
-fn emulate_instruction(
-    state: &mut State8080,
-    cycles: &mut isize,
-    special: &mut Special,
-) -> bool {
-    let opcode: u8 = state.memory[usize::from(state.pc)];
-    *cycles += isize::from(CYCLES[usize::from(opcode)]);
-    let pc: usize = usize::from(state.pc);
-
-    state.pc += SIZE[usize::from(opcode)] as u16;
-
-    //for debugging
-    /*
-    if pc == 0x118/* || pc == 0x100 || pc == 0x141 || pc == 0x17a || pc == 0x1a1 || pc == 0x1c0*/ {
-        println!("PC is at {:x?}. Press enter to continue", pc);
-        let mut buffer = String::new();
-        io::stdin().read_line(&mut buffer).expect("Did not enter a correct string");
-    }*/
-
-    match opcode {
-        // NOP
-        0x00 => instructions::nop(),
-
-        // LXI B, word
-        0x01 => {
-            instructions::lxi(&mut state.b, &mut state.c, &pc, &state.memory);
-        }
-
-        // INX B
-        0x03 => {
-            instructions::inx(&mut state.b, &mut state.c);
-        }
-
-        // INR B
-        0x04 => {
-            instructions::inr(&mut state.b, &mut state.cc);
-        }
-
-        // DCR B
-        0x05 => {
-            instructions::dcr(&mut state.b, &mut state.cc);
-        }
-
-        // MVI B
-        0x06 => {
-            instructions::mvi(&mut state.b, &mut state.memory, &pc);
-        }
-
-        // RLC
-        0x07 => {
-            state.cc.cy = (state.a >> 7) != 0;
-            state.a = (state.a >> 7) | (state.a << 1);
-        }
-
-        // DAD B
-        0x09 => {
-            instructions::dad(
-                &mut state.h,
-                &mut state.l,
-                &state.b,
-                &state.c,
-                &mut state.cc,
-            );
-        }
-
-        // LDAX B
-        0x0a => {
-            instructions::ldax(&mut state.a, &state.b, &state.c, &state.memory);
-        }
-
-        // INR C
-        0x0c => {
-            instructions::inr(&mut state.c, &mut state.cc);
-        }
-
-        // DCR C
-        0x0d => {
-            instructions::dcr(&mut state.c, &mut state.cc);
-        }
-
-        // MVI C
-        0x0e => {
-            instructions::mvi(&mut state.c, &state.memory, &pc);
-        }
-
-        // RRC
-        0x0f => {
-            state.cc.cy = (state.a & 0x1) != 0;
-            state.a = (state.a & 1 << 7) | (state.a >> 1);
-        }
-
-        // LXI D,word
-        0x11 => {
-            instructions::lxi(&mut state.d, &mut state.e, &pc, &state.memory);
-        }
-
-        // STAX D
-        0x12 => {
-            instructions::stax(&state.a, &state.d, &state.e, &mut state.memory);
-        }
-
-        // INX D
-        0x13 => {
-            instructions::inx(&mut state.d, &mut state.e);
-        }
-
-        // INR D
-        0x14 => instructions::inr(&mut state.d, &mut state.cc),
-
-        // DCR D
-        0x15 => instructions::dcr(&mut state.d, &mut state.cc),
-
-        // MVI D,D8
-        0x16 => {
-            instructions::mvi(&mut state.d, &state.memory, &pc);
-        }
-
-        // DAD D
-        0x19 => {
-            instructions::dad(
-                &mut state.h,
-                &mut state.l,
-                &state.d,
-                &state.e,
-                &mut state.cc,
-            );
-        }
-
-        // LDAX D
-        0x1a => {
-            instructions::ldax(&mut state.a, &state.d, &state.e, &state.memory);
-        }
-
-        // RAR
-        0x1f => {
-            let cy: bool = state.cc.cy;
-            state.cc.cy = state.a & 1 != 0;
-            state.a = (state.a >> 1) | ((cy as u8) << 7);
-        }
-
-        // LXI H,word
-        0x21 => {
-            instructions::lxi(&mut state.h, &mut state.l, &pc, &state.memory);
-        }
-
-        //SHLD
-        0x22 => {
-            let offset: usize =
-                (usize::from(state.memory[pc + 2]) << 8) | usize::from(state.memory[pc + 1]);
-            state.memory[offset] = state.l;
-            state.memory[offset + 1] = state.h;
-        }
-
-        // INX H
-        0x23 => {
-            instructions::inx(&mut state.h, &mut state.l);
-        }
-
-        // MVI H
-        0x26 => {
-            instructions::mvi(&mut state.h, &state.memory, &pc);
-        }
-
-        // DAD H
-        0x29 => {
-            let h = state.h;
-            let l = state.l;
-            instructions::dad(&mut state.h, &mut state.l, &h, &l, &mut state.cc);
-        }
-
-        // LHLD adr
-        0x2a => {
-            let offset: usize =
-                (usize::from(state.memory[pc + 2]) << 8) | usize::from(state.memory[pc + 1]);
-            state.l = state.memory[offset];
-            state.h = state.memory[offset + 1];
-        }
-
-        // DCX H
-        0x2b => {
-            instructions::dcx(&mut state.h, &mut state.l);
-        }
-
-        // INR L
-        0x2c => {
-            instructions::inr(&mut state.l, &mut state.cc);
-        }
-
-        // MVI L,D8
-        0x2e => {
-            instructions::mvi(&mut state.l, &state.memory, &pc);
-        }
-
-        // CMA (not)
-        0x2f => {
-            state.a = !state.a;
-        }
-
-        // LXI SP,word
-        0x31 => {
-            //instructions::lxi(&mut state.sp.to_be_bytes()[0], &mut state.sp.to_be_bytes()[0], &pc, &state.memory);
-            state.sp = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-        }
-
-        // STA adr
-        0x32 => {
-            let offset =
-                (usize::from(state.memory[pc + 2]) << 8) | usize::from(state.memory[pc + 1]);
-            state.memory[offset] = state.a;
-        }
-
-        // INR M
-        0x34 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            instructions::inr(&mut state.memory[offset], &mut state.cc);
-        }
-
-        // DCR M
-        0x035 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            instructions::dcr(&mut state.memory[offset], &mut state.cc);
-        }
-
-        // MVI M,D8
-        0x36 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.memory[offset] = state.memory[pc + 1];
-        }
-
-        // STC
-        0x37 => {
-            state.cc.cy = true;
-        }
-
-        // LDA adr
-        0x3a => {
-            let offset = usize::from(state.memory[pc + 2]) << 8 | usize::from(state.memory[pc + 1]);
-            state.a = state.memory[offset];
-        }
-
-        // INR A
-        0x3c => instructions::inr(&mut state.a, &mut state.cc),
-
-        // DCR A
-        0x3d => {
-            instructions::dcr(&mut state.a, &mut state.cc);
-        }
-
-        // MVI A,D8
-        0x3e => {
-            instructions::mvi(&mut state.a, &state.memory, &pc);
-        }
-
-        // CMC
-        0x3f => {
-            state.cc.cy = !state.cc.cy;
-        }
-
-        // MOV B,B
-        0x40 => (),
-
-        // MOV B,C
-        0x41 => state.b = state.c,
-
-        // MOV B,D
-        0x42 => state.b = state.d,
-
-        // MOV B,E
-        0x43 => state.b = state.e,
-
-        // MOV B,H
-        0x44 => state.b = state.h,
-
-        // MOV B,L
-        0x45 => state.b = state.l,
-
-        // MOV B,M
-        0x46 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.b = state.memory[offset];
-        }
-
-        // MOV B,A
-        0x47 => state.b = state.a,
-
-        // MOV C,M
-        0x4e => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.c = state.memory[offset];
-        }
-
-        // MOV C,A
-        0x4f => {
-            state.c = state.a;
-        }
-
-        // MOV D,M
-        0x56 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.d = state.memory[offset];
-        }
-
-        // MOV D,A
-        0x57 => {
-            state.d = state.a;
-        }
-
-        // MOV E,M
-        0x5e => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.e = state.memory[offset];
-        }
-
-        // MOV E,A
-        0x5f => {
-            state.e = state.a;
-        }
+fn fail(error: RomError) -> ! {
+    let error = EmulationError::from(error);
+    eprintln!("{}", error);
+    process::exit(exit_code(&error));
+}
 
-        // MOV H,C
-        0x61 => state.h = state.c,
+/// Exit status for each kind of emulation error, as listed in the usage.
+fn exit_code(error: &EmulationError) -> i32 {
+    match error {
+        EmulationError::IllegalOpcode { .. } => 3,
+        EmulationError::MemoryRange { .. } => 4,
+        EmulationError::StackOverflow { .. } | EmulationError::StackUnderflow { .. } => 5,
+        EmulationError::Io { .. } => 6,
+        EmulationError::Rom(_) => 7,
+        EmulationError::HaltDeadlock { .. } => 8,
+    }
+}
 
-        // MOV H,L
-        0x65 => state.h = state.l,
+/// Applies the checks chosen on the command line.
+fn configure<M: Bus, I: IoBus>(cpu: &mut Cpu8080<M, I>, options: &Options) {
+    cpu.set_undocumented(!options.strict);
+    cpu.set_stack_limits(options.stack.clone());
+}
 
-        // MOV H,M
-        0x66 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.h = state.memory[offset];
-        }
+/// Space Invaders ROMs from a directory, a zip archive or a single binary.
+fn load_invaders_roms(options: &Options) -> Result<Vec<Rom>, RomError> {
+    let path = options.rom.clone().unwrap_or_else(|| PathBuf::from("."));
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+    if path.is_dir() || is_zip {
+        rom::load_set(&path, &rom::SPACE_INVADERS)
+    } else {
+        Ok(vec![rom::load_binary(&path, options.load_addr())?])
+    }
+}
 
-        // MOV H,A
-        0x67 => {
-            state.h = state.a;
+fn run_invaders(options: &Options) {
+    let mut keymap = KeyMap::default();
+    let mut io = InvadersIo::new();
+    let config = options.config.clone().or_else(|| {
+        Some(PathBuf::from(CONFIG_FILE)).filter(|path| path.exists())
+    });
+    if let Some(config) = config {
+        if let Err(e) = input::load_config(&config, &mut keymap, &mut io.dip_switches) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
+    }
+    if !options.headless {
+        io.set_audio_sink(audio_sink(&options.samples));
+    }
+    let mut memory = MemoryMap::space_invaders();
+    if let Some(policy) = options.unmapped {
+        memory.unmap(0x4000, 0xc000);
+        memory.set_unmapped_policy(policy);
+    }
+    let mut cpu = Cpu8080::with_devices(Recorder::new(memory), io);
+    configure(&mut cpu, options);
 
-        // MOV L,B
-        0x68 => state.l = state.b,
-
-        // MOV L,C
-        0x69 => state.l = state.c,
-
-        // MOV L,A
-        0x6f => {
-            state.l = state.a;
-        }
+    //load rom to memory
+    let roms = load_invaders_roms(options).unwrap_or_else(|e| fail(e));
+    for rom in &roms {
+        cpu.memory_mut().inner_mut().load(rom.addr, &rom.data);
+    }
+    let rom_id = savestate::rom_id(&roms);
 
-        // MOV M,B
-        0x70 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.memory[offset] = state.b;
-        }
+    // minifb window
+    let mut window = if options.headless {
+        None
+    } else {
+        let scale = match options.scale {
+            2 => Scale::X2,
+            4 => Scale::X4,
+            8 => Scale::X8,
+            _ => Scale::X1,
+        };
+        let mut window = Window::new(
+            "8080",
+            screen::WIDTH,
+            screen::HEIGHT,
+            WindowOptions {
+                scale,
+                ..WindowOptions::default()
+            },
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("cannot open window: {}", e);
+            process::exit(1);
+        });
+        // frames are paced against the emulated clock, not the window
+        window.limit_update_rate(None);
+        Some(window)
+    };
 
-        // MOV M,C
-        0x71 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.memory[offset] = state.c;
+    let mut scheduler = invaders::scheduler();
+    if let Some(path) = &options.load_state {
+        if let Err(e) = savestate::load_file(path, rom_id, &mut [&mut cpu, &mut scheduler]) {
+            eprintln!("{}", e);
+            process::exit(1);
         }
+    }
+    let mut replay = options
+        .play_movie
+        .as_ref()
+        .map(|path| (play_movie(path, rom_id, &mut cpu, &mut scheduler), 0));
+    let mut recording = options.record_movie.as_ref().map(|_| {
+        let start = match options.load_state {
+            Some(_) => Start::State(savestate::encode(rom_id, &[&cpu, &scheduler])),
+            None => Start::PowerOn(cpu.io().dip_switches),
+        };
+        Movie::new(rom_id, start)
+    });
+    let first_frame = scheduler.frames();
+    let mut failed = false;
+    let mut slot: u8 = 0;
+    let mut rewind = Rewind::new(options.rewind_memory);
+    let clock_hz = (f64::from(invaders::CPU_CLOCK_HZ) * options.speed) as u32;
+    let mut pacer = Pacer::new(clock_hz.max(1), invaders::CYCLES_PER_FRAME);
+    let mut total_instructions: u64 = 0;
+    let mut session = new_debugger(options);
+    let mut tracer = new_tracer(options);
+    let mut reporter = new_reporter(options);
+    let mut lockstep = new_lockstep(options);
 
-        // MOV M,A
-        0x77 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            if offset >= state.memory.len() {
-                println!("offset out of bounds({:x?})", offset);
-                return false;
+    //main emulation loop
+    'emulation: while window.as_ref().is_none_or(|window| window.is_open()) {
+        let rewinding = options.rewind_memory > 0
+            && replay.is_none()
+            && window.as_ref().is_some_and(|window| window.is_key_down(REWIND_KEY));
+        if rewinding {
+            if let Some(snapshot) = rewind.pop() {
+                savestate::restore_payload(snapshot, &mut [&mut cpu, &mut scheduler])
+                    .expect("the machine restores its own state");
+                if let Some(movie) = recording.as_mut() {
+                    movie.frames.truncate(scheduler.frames().saturating_sub(first_frame) as usize);
+                }
             }
-            state.memory[offset] = state.a;
-        }
-
-        // MOV A,B
-        0x78 => {
-            state.a = state.b;
-        }
-
-        // MOV A,C
-        0x79 => {
-            state.a = state.c;
-        }
-
-        // MOV A,D
-        0x7a => {
-            state.a = state.d;
-        }
-
-        // MOV A,E
-        0x7b => {
-            state.a = state.e;
-        }
-
-        // MOV A,H
-        0x7c => {
-            state.a = state.h;
-        }
-
-        // MOV A,L
-        0x7d => {
-            state.a = state.l;
-        }
-
-        // MOV A,M
-        0x7e => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            state.a = state.memory[offset];
-        }
-
-        // ADD B
-        0x80 => {
-            instructions::add(&mut state.a, &state.b, &mut state.cc);
-        }
-
-        // ADD C
-        0x81 => {
-            instructions::add(&mut state.a, &state.c, &mut state.cc);
-        }
-
-        // ADD D
-        0x82 => {
-            instructions::add(&mut state.a, &state.d, &mut state.cc);
-        }
-
-        // ADD E
-        0x83 => {
-            instructions::add(&mut state.a, &state.e, &mut state.cc);
-        }
-
-        // ADD H
-        0x84 => {
-            instructions::add(&mut state.a, &state.h, &mut state.cc);
-        }
-
-        // ADD L
-        0x85 => {
-            instructions::add(&mut state.a, &state.l, &mut state.cc);
-        }
-
-        // ADD M
-        0x86 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            instructions::add(&mut state.a, &state.memory[offset], &mut state.cc);
-        }
-
-        // ADD A
-        0x87 => {
-            let a = state.a;
-            instructions::add(&mut state.a, &a, &mut state.cc);
-        }
-
-        // SUB A
-        0x97 => {
-            let a = state.a;
-            instructions::sub(&mut state.a, &a, &mut state.cc);
-        }
-
-        // ANA B
-        0xa0 => {
-            instructions::ana(&mut state.a, &state.b, &mut state.cc);
-        }
-
-        // ANA M
-        0xa6 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            let m = state.memory[offset];
-            instructions::ana(&mut state.a, &m, &mut state.cc);
-        }
-
-        // ANA A
-        0xa7 => {
-            let a = state.a;
-            instructions::ana(&mut state.a, &a, &mut state.cc);
-        }
-
-        // XRA B
-        0xa8 => instructions::xra(&mut state.a, &state.b, &mut state.cc),
-
-        // XRA A
-        0xaf => {
-            let a = state.a;
-            instructions::xra(&mut state.a, &a, &mut state.cc);
-        }
-
-        // ORA B
-        0xb0 => {
-            instructions::ora(&mut state.a, &state.b, &mut state.cc);
-        }
-
-        // ORA H
-        0xb4 => instructions::ora(&mut state.a, &state.h, &mut state.cc),
-
-        // ORA L
-        0xb5 => instructions::ora(&mut state.a, &state.l, &mut state.cc),
-
-        // ORA M
-        0xb6 => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            let m = state.memory[offset];
-            instructions::ora(&mut state.a, &m, &mut state.cc);
-        }
-
-        // CMP B
-        0xb8 => {
-            instructions::cmp(&mut state.a, &state.b, &mut state.cc);
-        }
-
-        // CMP H
-        0xbc => {
-            instructions::cmp(&mut state.a, &state.h, &mut state.cc);
-        }
-
-        // CMP H
-        0xbe => {
-            let offset = (usize::from(state.h) << 8) | usize::from(state.l);
-            let m = state.memory[offset];
-            instructions::cmp(&mut state.a, &m, &mut state.cc);
-        }
-
-        // RNZ
-        0xc0 => {
-            if !state.cc.z {
-                let sp: usize = usize::from(state.sp);
-                state.pc = (u16::from(state.memory[sp + 1]) << 8) | u16::from(state.memory[sp]);
-                state.sp += 2;
-            } else {
-                *cycles -= 6;
+        } else {
+            // inputs only change between frames, so a movie replays bit for bit
+            if let Some((movie, next)) = replay.as_mut() {
+                match movie.frames.get(*next) {
+                    Some(buttons) => cpu.io_mut().set_buttons(*buttons),
+                    None => {
+                        failed = !movie_in_sync(movie, &cpu, &scheduler);
+                        break 'emulation;
+                    }
+                }
+                *next += 1;
             }
-        }
-
-        // POP B
-        0xc1 => {
-            let sp: usize = usize::from(state.sp);
-            state.c = state.memory[sp];
-            state.b = state.memory[sp + 1];
-            state.sp += 2;
-        }
-
-        // JNZ adr
-        0xc2 => {
-            if !state.cc.z {
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
+            if let Some(movie) = recording.as_mut() {
+                movie.frames.push(cpu.io().buttons());
             }
         }
-
-        // JMP adr
-        0xc3 => {
-            state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-        }
-
-        // CNZ adr
-        0xc4 => {
-            if !state.cc.z {
-                let sp: usize = usize::from(state.sp);
-                //let ret = state.pc + 2; <-- maybe this is wrong
-                let ret = state.pc;
-                state.memory[sp - 1] = ret.to_be_bytes()[0];
-                state.memory[sp - 2] = ret.to_be_bytes()[1];
-                state.sp -= 2;
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
-                *cycles -= 6;
+        // run one frame, the scheduler raises the mid-screen and end-of-screen interrupts
+        while !rewinding && !scheduler.take_frame_done() {
+            if !debug_break(&mut session, &mut cpu, &reporter) {
+                break 'emulation;
             }
-        }
-
-        // PUSH B
-        0xc5 => {
-            let sp: usize = usize::from(state.sp);
-            state.memory[sp - 1] = state.b;
-            state.memory[sp - 2] = state.c;
-            state.sp -= 2;
-        }
-
-        // ADI D8
-        0xc6 => {
-            let offset = usize::from(pc + 1);
-            let result: u16 = u16::from(state.a) + u16::from(state.memory[offset]);
-            state.a = result as u8;
-            instructions::set_arithmetic_flags(&mut state.cc, &result);
-        }
-
-        // RZ
-        0xc8 => {
-            if state.cc.z {
-                let sp: usize = usize::from(state.sp);
-                state.pc = (u16::from(state.memory[sp + 1]) << 8) | u16::from(state.memory[sp]);
-                state.sp += 2;
-            } else {
-                *cycles -= 6;
+            if !compare_step(&mut lockstep, &cpu, &reporter, scheduler.total_cycles(), &mut failed) {
+                break 'emulation;
             }
-        }
-
-        // RET
-        0xc9 => {
-            let sp: usize = usize::from(state.sp);
-            state.pc = (u16::from(state.memory[sp + 1]) << 8) | u16::from(state.memory[sp]);
-            state.sp += 2;
-        }
-
-        // JZ adr
-        0xca => {
-            if state.cc.z {
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
+            let info = Instructioninfo::capture(&cpu, reporter.instructions(), scheduler.total_cycles());
+            trace_step(&mut tracer, &info);
+            reporter.record(info);
+            let (pc, bytes) = (cpu.pc(), cpu.next_instruction());
+            match scheduler.step(&mut cpu) {
+                Ok(_) => {}
+                Err(error) if trap(&mut session, &error) => {}
+                Err(error) => {
+                    finish_trace(tracer);
+                    crash(&cpu, &reporter, options, &error);
+                }
             }
+            check_watchpoints(&mut session, &mut cpu, pc, bytes);
+            total_instructions += 1;
         }
-
-        // CZ adr
-        0xcc => {
-            if state.cc.z {
-                let sp: usize = usize::from(state.sp);
-                let ret = state.pc;
-                state.memory[sp - 1] = ret.to_be_bytes()[0];
-                state.memory[sp - 2] = ret.to_be_bytes()[1];
-                state.sp -= 2;
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
-                *cycles -= 6;
-            }
+        let frame = scheduler.frames().saturating_sub(first_frame);
+        if !rewinding && options.rewind_memory > 0 {
+            rewind.push(savestate::payload(&[&cpu, &scheduler]));
         }
-
-        // CALL adr
-        0xcd => {
-            let sp: usize = usize::from(state.sp);
-            let ret = state.pc;
-            state.memory[sp - 1] = ret.to_be_bytes()[0];
-            state.memory[sp - 2] = ret.to_be_bytes()[1];
-            state.sp -= 2;
-            state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
+        if !rewinding && options.capture.contains(&frame) {
+            capture(cpu.memory(), options, frame);
         }
+        poll_gdb(&mut session);
 
-        // RNC
-        0xd0 => {
-            if !state.cc.cy {
-                let sp: usize = usize::from(state.sp);
-                state.pc = (u16::from(state.memory[sp + 1]) << 8) | u16::from(state.memory[sp]);
-                state.sp += 2;
-            } else {
-                *cycles -= 6;
+        if let Some(window) = window.as_mut() {
+            window
+                .update_with_buffer(&screen::render(cpu.memory()), screen::WIDTH, screen::HEIGHT)
+                .unwrap_or_else(|e| {
+                    panic!("{}", e);
+                });
+            if replay.is_none() {
+                keymap.poll(window, cpu.io_mut());
             }
-        }
-
-        // POP D
-        0xd1 => {
-            let sp: usize = usize::from(state.sp);
-            state.e = state.memory[sp];
-            state.d = state.memory[sp + 1];
-            state.sp += 2;
-        }
-
-        // JNC adr
-        0xd2 => {
-            if !state.cc.cy {
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
+            if window.is_key_pressed(DUMP_KEY, KeyRepeat::No) {
+                let path = reporter.dump_path();
+                match reporter.write(&cpu, &format!("{:?} pressed", DUMP_KEY), &path) {
+                    Ok(()) => println!("report written to {}", path.display()),
+                    Err(e) => eprintln!("{}: {}", path.display(), e),
+                }
             }
-        }
-
-        // OUT D8
-        0xd3 => {
-            special.machine_out(&state.memory[pc + 1], &state.a);
-        }
-
-        // CNC adr
-        0xd4 => {
-            if !state.cc.cy {
-                let sp: usize = usize::from(state.sp);
-                let ret = state.pc;
-                state.memory[sp - 1] = ret.to_be_bytes()[0];
-                state.memory[sp - 2] = ret.to_be_bytes()[1];
-                state.sp -= 2;
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
-                *cycles -= 6;
+            if window.is_key_pressed(CAPTURE_KEY, KeyRepeat::No) {
+                capture(cpu.memory(), options, frame);
             }
+            let movie_active = replay.is_some() || recording.is_some();
+            state_keys(window, &mut slot, options, rom_id, movie_active, &mut cpu, &mut scheduler);
+            pacer.wait();
         }
 
-        // PUSH D
-        0xd5 => {
-            let sp: usize = usize::from(state.sp);
-            state.memory[sp - 1] = state.d;
-            state.memory[sp - 2] = state.e;
-            state.sp -= 2;
+        if options.max_instructions.is_some_and(|max| total_instructions >= max) {
+            println!("total_instructions ({}) reached the limit, exiting...", total_instructions);
+            break;
         }
-
-        // SUI D8
-        0xd6 => {
-            let offset = usize::from(pc + 1);
-            let a = u16::from(state.a);
-            let result: u16 = a.wrapping_sub(u16::from(state.memory[offset]));
-            state.a = result.to_be_bytes()[1];
-            instructions::set_arithmetic_flags(&mut state.cc, &result);
+        if options.frames.is_some_and(|frames| frame >= frames) {
+            println!("ran {} frames, exiting...", frame);
+            break;
         }
-
-        // RC
-        0xd8 => {
-            if state.cc.cy {
-                let sp: usize = usize::from(state.sp);
-                state.pc = (u16::from(state.memory[sp + 1]) << 8) | u16::from(state.memory[sp]);
-                state.sp += 2;
-            } else {
-                *cycles -= 6;
-            }
+    }
+    if let (Some(mut movie), Some(path)) = (recording, &options.record_movie) {
+        movie.checksum = movie::checksum(&[&cpu, &scheduler]);
+        match movie.save(path) {
+            Ok(()) => println!("recorded {} frames to {}", movie.frames.len(), path.display()),
+            Err(e) => eprintln!("{}", e),
         }
+    }
+    finish_trace(tracer);
+    end_session(session);
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// Saves the screen as `frame-N.png` in the capture directory.
+fn capture<M: Bus>(memory: &M, options: &Options, frame: u64) {
+    let path = options.capture_dir.join(format!("frame-{}.png", frame));
+    let written = fs::create_dir_all(&options.capture_dir).and_then(|_| screen::write_png(&path, &screen::render(memory)));
+    match written {
+        Ok(()) => println!("screen saved to {}", path.display()),
+        Err(e) => eprintln!("{}: {}", path.display(), e),
+    }
+}
 
-        // JC adr
-        0xda => {
-            if state.cc.cy {
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
-            } else {
+/// Loads a movie and puts the machine into its starting state.
+fn play_movie<M: Bus + Snapshot>(
+    path: &Path,
+    rom_id: u32,
+    cpu: &mut Cpu8080<M, InvadersIo>,
+    scheduler: &mut Scheduler,
+) -> Movie {
+    let movie = Movie::load(path, rom_id).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    match &movie.start {
+        Start::PowerOn(dips) => cpu.io_mut().dip_switches = *dips,
+        Start::State(state) => {
+            if let Err(e) = savestate::decode(state, rom_id, &mut [cpu, scheduler]) {
+                eprintln!("{}: {}", path.display(), e);
+                process::exit(1);
             }
         }
+    }
+    movie
+}
 
-        // IN D8
-        0xdb => {
-            state.a = special.machine_in(&state.memory[pc + 1], state);
-        }
+/// Compares the machine state after the last frame of a movie with the recording.
+fn movie_in_sync<M: Bus + Snapshot>(movie: &Movie, cpu: &Cpu8080<M, InvadersIo>, scheduler: &Scheduler) -> bool {
+    let checksum = movie::checksum(&[cpu, scheduler]);
+    if checksum == movie.checksum {
+        println!("replayed {} frames, final state matches", movie.frames.len());
+    } else {
+        eprintln!(
+            "movie desynced after {} frames: state checksum {:08x}, expected {:08x}",
+            movie.frames.len(),
+            checksum,
+            movie.checksum
+        );
+    }
+    checksum == movie.checksum
+}
 
-        //SBI D8
-        0xde => {
-            let a: u16 = u16::from(state.a);
-            let result: u16 = a
-                .wrapping_sub(u16::from(state.memory[pc + 1]))
-                .wrapping_sub(u16::from(state.cc.cy));
-            state.a = result.to_be_bytes()[1];
-            instructions::set_arithmetic_flags(&mut state.cc, &result);
+/// Handles the save state hotkeys. States are not loaded while a movie records or
+/// plays, that would break its inputs.
+fn state_keys<M: Bus + Snapshot>(
+    window: &Window,
+    slot: &mut u8,
+    options: &Options,
+    rom_id: u32,
+    movie_active: bool,
+    cpu: &mut Cpu8080<M, InvadersIo>,
+    scheduler: &mut Scheduler,
+) {
+    let path = options.state_dir.join(format!("slot{}.sav", slot));
+    if window.is_key_pressed(SAVE_STATE_KEY, KeyRepeat::No) {
+        match savestate::save_file(&path, rom_id, &[&*cpu, &*scheduler]) {
+            Ok(()) => println!("state saved to slot {}", slot),
+            Err(e) => eprintln!("{}", e),
         }
-
-        // POP H
-        0xe1 => {
-            let sp: usize = usize::from(state.sp);
-            state.l = state.memory[sp];
-            state.h = state.memory[sp + 1];
-            state.sp += 2;
+    }
+    if window.is_key_pressed(LOAD_STATE_KEY, KeyRepeat::No) {
+        if movie_active {
+            eprintln!("cannot load a state while a movie is recording or playing");
+        } else {
+            match savestate::load_file(&path, rom_id, &mut [cpu, scheduler]) {
+                Ok(()) => println!("state loaded from slot {}", slot),
+                Err(e) => eprintln!("{}", e),
+            }
         }
+    }
+    if window.is_key_pressed(PREV_SLOT_KEY, KeyRepeat::No) {
+        *slot = (*slot + STATE_SLOTS - 1) % STATE_SLOTS;
+        println!("save state slot {}", slot);
+    }
+    if window.is_key_pressed(NEXT_SLOT_KEY, KeyRepeat::No) {
+        *slot = (*slot + 1) % STATE_SLOTS;
+        println!("save state slot {}", slot);
+    }
+}
 
-        // XTHL
-        0xe3 => {
-            let bufferh = state.h;
-            let bufferl = state.l;
-            state.h = state.memory[usize::from(state.sp + 1)];
-            state.l = state.memory[usize::from(state.sp)];
-            state.memory[usize::from(state.sp + 1)] = bufferh;
-            state.memory[usize::from(state.sp)] = bufferl;
+/// Runs a CP/M program or a bare binary until it warm boots (CP/M), halts with
+/// interrupts disabled or reaches the instruction limit. CP/M programs get the
+/// console functions of the BDOS.
+fn run_program(options: &Options) {
+    let path = options.rom.as_ref().expect("checked by cli::parse");
+    let rom = rom::load_binary(path, options.load_addr()).unwrap_or_else(|e| fail(e));
+    let mut memory = MemoryMap::unmapped();
+    memory.map_ram(0x0000, options.ram);
+    memory.set_unmapped_policy(options.unmapped.unwrap_or_default());
+    let mut cpu = Cpu8080::with_memory(Recorder::new(memory));
+    configure(&mut cpu, options);
+    if options.machine == Machine::Cpm {
+        cpm::install(cpu.memory_mut().inner_mut());
+    }
+    cpu.memory_mut().inner_mut().load(rom.addr, &rom.data);
+    cpu.set_pc(rom.addr);
+
+    let mut total_instructions: u64 = 0;
+    let mut total_cycles: u64 = 0;
+    let mut session = new_debugger(options);
+    let mut tracer = new_tracer(options);
+    let mut reporter = new_reporter(options);
+    let mut lockstep = new_lockstep(options);
+    let mut failed = false;
+    loop {
+        if options.machine == Machine::Cpm && cpu.pc() == 0x0000 {
+            println!("\nwarm boot after {} instructions", total_instructions);
+            break;
         }
-
-        // PUSH H
-        0xe5 => {
-            let sp: usize = usize::from(state.sp);
-            state.memory[sp - 1] = state.h;
-            state.memory[sp - 2] = state.l;
-            state.sp -= 2;
+        if options.machine == Machine::Cpm && cpu.pc() == cpm::BDOS {
+            if let Err(e) = cpm::bdos(&mut cpu, &mut io::stdout()) {
+                eprintln!("console: {}", e);
+                process::exit(1);
+            }
+            continue;
         }
-
-        // ANI D8
-        0xe6 => {
-            instructions::ana(&mut state.a, &state.memory[pc + 1], &mut state.cc);
+        if cpu.halted() && !cpu.int_enabled() {
+            println!("halted at {:04x} after {} instructions", cpu.pc(), total_instructions);
+            break;
         }
-
-        // PCHL
-        0xe9 => {
-            state.pc = (u16::from(state.h) << 8) | u16::from(state.l);
+        if options.max_instructions.is_some_and(|max| total_instructions >= max) {
+            println!("total_instructions ({}) reached the limit, exiting...", total_instructions);
+            break;
         }
-
-        // XCHG
-        0xeb => {
-            let bufferd = state.d;
-            let buffere = state.e;
-            state.d = state.h;
-            state.e = state.l;
-            state.h = bufferd;
-            state.l = buffere;
+        if !debug_break(&mut session, &mut cpu, &reporter) {
+            break;
         }
-
-        // POP PSW
-        //TODO : ÜBERPRÜFEN!
-        0xf1 => {
-            let sp = usize::from(state.sp);
-            state.a = state.memory[sp + 1];
-            let psw = state.memory[sp];
-            state.cc.cy = (psw & 0b1) != 0;
-            state.cc.p = (psw & 0b100) != 0;
-            state.cc.ac = (psw & 0b10000) != 0;
-            state.cc.z = (psw & 0b1000000) != 0;
-            state.cc.s = (psw & 0b10000000) != 0;
-            state.sp += 2;
+        if !compare_step(&mut lockstep, &cpu, &reporter, total_cycles, &mut failed) {
+            break;
         }
-
-        // PUSH PSW
-        0xf5 => {
-            state.memory[usize::from(state.sp - 1)] = state.a;
-            let mut psw: u8 = 0;
-            if state.cc.cy {
-                psw = psw | 0b1;
-            }
-            if state.cc.p {
-                psw = psw | 0b100;
-            }
-            if state.cc.ac {
-                psw = psw | 0b10000;
+        let info = Instructioninfo::capture(&cpu, reporter.instructions(), total_cycles);
+        trace_step(&mut tracer, &info);
+        reporter.record(info);
+        let (pc, bytes) = (cpu.pc(), cpu.next_instruction());
+        match cpu.step() {
+            Ok(cycles) => total_cycles += u64::from(cycles),
+            Err(error) if trap(&mut session, &error) => total_cycles += u64::from(error.cycles()),
+            Err(error) => {
+                finish_trace(tracer);
+                crash(&cpu, &reporter, options, &error);
             }
-            if state.cc.z {
-                psw = psw | 0b1000000;
-            }
-            if state.cc.s {
-                psw = psw | 0b10000000;
-            }
-            psw = psw | 0b10;
-            state.memory[usize::from(state.sp - 2)] = psw;
-            state.sp -= 2;
         }
-
-        // TODO Besser machen
-        // ORI D8
-        0xf6 => {
-            instructions::ora(&mut state.a, &state.memory[pc + 1], &mut state.cc);
+        check_watchpoints(&mut session, &mut cpu, pc, bytes);
+        total_instructions += 1;
+        if total_instructions.is_multiple_of(0x10000) {
+            poll_gdb(&mut session);
         }
+    }
+    finish_trace(tracer);
+    end_session(session);
+    if failed {
+        process::exit(1);
+    }
+}
 
-        // SPHL
-        0xf9 => {
-            state.sp = (u16::from(state.h) << 8) | u16::from(state.l);
-        }
+fn new_lockstep(options: &Options) -> Option<Lockstep<BufReader<File>>> {
+    let path = options.compare.as_ref()?;
+    let file = File::open(path).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+    Some(Lockstep::new(BufReader::new(file)))
+}
 
-        // JM adr
-        0xfa => {
-            if state.cc.s {
-                state.pc = (u16::from(state.memory[pc + 2]) << 8) | u16::from(state.memory[pc + 1]);
+/// Checks the CPU against the reference log before an instruction. Returns false
+/// to stop, when the log has ended or the CPU left it, setting `failed` then.
+fn compare_step<M: Bus, I: IoBus>(
+    lockstep: &mut Option<Lockstep<BufReader<File>>>,
+    cpu: &Cpu8080<M, I>,
+    reporter: &Reporter,
+    cycles: u64,
+    failed: &mut bool,
+) -> bool {
+    let Some(lockstep) = lockstep.as_mut() else {
+        return true;
+    };
+    match lockstep.check(cpu, cycles) {
+        Ok(true) => true,
+        Ok(false) => {
+            println!("reference log ended, {} instructions matched", lockstep.instructions());
+            false
+        }
+        Err(LockstepError::Diverged(divergence)) => {
+            eprint!("{}", divergence);
+            eprintln!("  state: {}", debugger::registers(cpu.state()));
+            eprintln!("preceding instructions:");
+            for info in reporter.history() {
+                eprintln!("{}", info);
             }
+            *failed = true;
+            false
         }
-
-        // EI
-        0xfb => {
-            state.int_enable = true;
+        Err(e) => {
+            eprintln!("{}", e);
+            *failed = true;
+            false
         }
+    }
+}
 
-        //CPI D8
-        0xfe => {
-            instructions::cmp(&mut state.a, &state.memory[pc + 1], &mut state.cc);
-        }
+/// The debugger and what drives it, the console prompt or a GDB client.
+struct DebugSession {
+    debugger: Debugger,
+    gdb: Option<GdbStub>,
+}
 
-        _ => {
-            unimplemented_instruction(opcode, pc);
-            return false;
-        }
+fn new_debugger(options: &Options) -> Option<DebugSession> {
+    if !options.debugger() {
+        return None;
     }
-
-    /*for (i,item) in state.memory[0..0x3fff].iter().enumerate() {
-        if state.memory[i] != memory[i] {
-            println!("memory at addr: {:x?} changed {:x?} -> {:x?}", i, memory[i], state.memory[i]);
-        }
+    let mut debugger = Debugger::new(options.debug || options.gdb_port.is_some());
+    for addr in &options.breakpoints {
+        debugger.add_breakpoint(*addr);
+    }
+    for watchpoint in &options.watchpoints {
+        debugger.add_watchpoint(watchpoint.clone());
     }
-    println!(""); */
+    let gdb = options.gdb_port.map(|port| {
+        println!("waiting for gdb on 127.0.0.1:{}", port);
+        GdbStub::accept(("127.0.0.1", port)).unwrap_or_else(|e| {
+            eprintln!("gdb: {}", e);
+            process::exit(1);
+        })
+    });
+    Some(DebugSession { debugger, gdb })
+}
 
-    return true;
+/// Hands the memory accesses of the instruction at `pc` to the debugger's watchpoints.
+fn check_watchpoints<M: Bus, I: IoBus>(
+    session: &mut Option<DebugSession>,
+    cpu: &mut Cpu8080<Recorder<M>, I>,
+    pc: u16,
+    bytes: [u8; 3],
+) {
+    let Some(session) = session.as_mut() else {
+        return;
+    };
+    let accesses = cpu.memory_mut().take_accesses();
+    if let Err(e) = session.debugger.check_accesses(pc, bytes, &accesses, &mut io::stdout()) {
+        eprintln!("debugger: {}", e);
+    }
 }
 
-fn parity(x: u8) -> bool {
-    let mut one_bits: u8 = 0;
-    for i in 0..8 {
-        one_bits += (x >> i) & 0x1;
+/// Stops at the next instruction if the GDB client asked for it. The socket is only
+/// polled now and then, checking it on every instruction would be slow.
+fn poll_gdb(session: &mut Option<DebugSession>) {
+    if let Some(DebugSession { debugger, gdb: Some(gdb) }) = session.as_mut() {
+        if gdb.interrupted() {
+            debugger.pause();
+        }
     }
-    return (one_bits & 0x1) != 0;
 }
 
-fn unimplemented_instruction(opcode: u8, pc: usize) {
-    println!(
-        "Unimplemented Instruction: opcode: {:x?} pc: {:x?}",
-        opcode, pc
-    );
+/// Tells a GDB client that the program is gone.
+fn end_session(session: Option<DebugSession>) {
+    if let Some(DebugSession { gdb: Some(mut gdb), .. }) = session {
+        gdb.exited(0);
+    }
 }
 
-fn generate_interrupt(state: &mut State8080, interrupt_type: &mut bool) {
-    state.memory[usize::from(state.sp) - 1] = state.pc.to_be_bytes()[0];
-    state.memory[usize::from(state.sp) - 2] = state.pc.to_be_bytes()[1];
-    state.sp -= 2;
-    state.pc = 8 * ((*interrupt_type as u16) + 1);
-    state.int_enable = false;
+/// Stops in the debugger after an access to unmapped memory caught by the trap
+/// policy. Returns false when there is no debugger to stop in.
+fn trap(session: &mut Option<DebugSession>, error: &EmulationError) -> bool {
+    let (Some(session), EmulationError::MemoryRange { .. }) = (session.as_mut(), error) else {
+        return false;
+    };
+    println!("trapped: {}", error);
+    session.debugger.pause();
+    true
 }
 
-fn dump_memory(state: &State8080, total_instructions: &usize, opcode: &u8, pc: &u16, cycles: &isize) {
-    let mut memString = String::new();
-    for n in 0..16384 {
-        if n % 16 == 0 {
-            let mut hexString = format!("{:x?}", n);
-            while (hexString.len() < 4) {
-                hexString.insert(0, '0');
+/// Lets the debugger stop before the next instruction. Returns false when the user quits.
+fn debug_break<M: Bus, I: IoBus>(
+    session: &mut Option<DebugSession>,
+    cpu: &mut Cpu8080<Recorder<M>, I>,
+    reporter: &Reporter,
+) -> bool {
+    let Some(DebugSession { debugger, gdb }) = session.as_mut() else {
+        return true;
+    };
+    if debugger.should_break(cpu.pc()) {
+        let resume = match gdb {
+            // the stub has dropped the client, the program runs on without it
+            Some(gdb) => gdb.serve(cpu, debugger).or_else(|e| {
+                eprintln!("gdb: {}", e);
+                Ok(Resume::Run)
+            }),
+            None => debugger.prompt(cpu, reporter, &mut io::stdin().lock(), &mut io::stdout()),
+        };
+        match resume {
+            Ok(Resume::Run) => {}
+            Ok(Resume::Quit) => return false,
+            Err(e) => {
+                eprintln!("debugger: {}", e);
+                return false;
             }
-            memString.push_str(format!("\n{}  ", hexString).as_str());
-        }
-        let mut tempString = format!("{:x?}", state.memory[n]);
-        if (tempString.len() == 1) {
-            tempString.insert(0, '0');
         }
-        memString.push_str(&tempString.as_str());
     }
-    println!("Instruction: {} op: {:x?} pc:{:x?}", *total_instructions, *opcode, *pc);
-    println!("a:{:x?} bc:{:x?}{:x?} de:{:x?}{:x?} hl:{:x?}{:x?} sp:{:x?}", state.a, state.b, state.c, state.d, state.e, state.h, state.l, state.sp);
-    println!("cycles:{}", *cycles);
-    println!("------memdump------");
-    print!("{}", memString);
-    exit(0);
+    // memory accesses are only recorded while there are watchpoints to check them against
+    let watching = !debugger.watchpoints().is_empty();
+    cpu.memory_mut().set_enabled(watching);
+    true
 }
 
-struct Special {
-    shift_offset: u8,
-    shift0: u8,
-    shift1: u8,
+fn new_reporter(options: &Options) -> Reporter {
+    let mut reporter = Reporter::new(options.dump_format, options.history);
+    reporter.set_range(options.dump_range.clone());
+    reporter
 }
-impl Special {
-    fn machine_out(&mut self, port: &u8, value: &u8) {
-        //println!("OUTPORT: {:?}", port);
-        match port {
-            2 => {
-                self.shift_offset = *value & 0x7;
-            }
-            4 => {
-                self.shift0 = self.shift1;
-                self.shift1 = *value;
-            }
-            _ => {
-                if *port != 3 && *port != 5 && *port != 6 {
-                    println!("unimplemented special port(out): {:?}", port);
-                }
-                //exit(1);
-            }
-        }
+
+/// Writes the crash report for `error` and exits with its status.
+fn crash<M: Bus, I: IoBus>(cpu: &Cpu8080<M, I>, reporter: &Reporter, options: &Options, error: &EmulationError) -> ! {
+    let reason = error.to_string();
+    eprintln!("emulation aborted: {}", reason);
+    let path = &options.crash_report;
+    match reporter.write(cpu, &reason, path) {
+        Ok(()) => eprintln!("report written to {}", path.display()),
+        Err(e) => eprintln!("{}: {}", path.display(), e),
     }
-    fn machine_in(&mut self, port: &u8, state: &State8080) -> u8 {
-        //println!("INPORT: {:?}", port);
-        let mut a: u8 = 0;
-        match port {
-            3 => {
-                let v: u16 = (u16::from(self.shift1) << 8) | u16::from(self.shift0);
-                let buffer: u16 = (v >> (8 - self.shift_offset)) & 0xff;
-                a = buffer.to_be_bytes()[1];
-            }
-            _ => {
-                if *port != 1 && *port != 2 {
-                    println!("unimplemented special port(in): {:?}", port);
-                }
-                //exit(1);
-            }
+    process::exit(exit_code(error));
+}
+
+fn new_tracer(options: &Options) -> Option<Tracer> {
+    let path = options.trace.as_ref()?;
+    let tracer = Tracer::create(path, options.trace_format, options.trace_filter.clone()).unwrap_or_else(|e| {
+        eprintln!("{}: {}", path.display(), e);
+        process::exit(1);
+    });
+    Some(tracer)
+}
+
+/// Records the next instruction. A trace that cannot be written is dropped, the emulation goes on.
+fn trace_step(tracer: &mut Option<Tracer>, info: &Instructioninfo) {
+    if let Some(t) = tracer.as_mut() {
+        if let Err(e) = t.record(info) {
+            eprintln!("trace: {}", e);
+            *tracer = None;
         }
-        return a;
     }
 }
 
-const CYCLES: [u8; 256] = [
-    4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,
-    4,  10, 7,  5,  5,  5,  7,  4,  4,  10, 7,  5,  5,  5,  7,  4,
-    4,  10, 16, 5,  5,  5,  7,  4,  4,  10, 16, 5,  5,  5,  7,  4,
-    4,  10, 13, 5,  10, 10, 10, 4,  4,  10, 13, 5,  5,  5,  7,  4,
-
-    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,
-    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,
-    5,  5,  5,  5,  5,  5,  7,  5,  5,  5,  5,  5,  5,  5,  7,  5,
-    7,  7,  7,  7,  7,  7,  7,  7,  5,  5,  5,  5,  5,  5,  7,  5,
+fn finish_trace(tracer: Option<Tracer>) {
+    if let Some(Err(e)) = tracer.map(|mut t| t.flush()) {
+        eprintln!("trace: {}", e);
+    }
+}
 
-    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
-    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
-    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
-    4,  4,  4,  4,  4,  4,  7,  4,  4,  4,  4,  4,  4,  4,  7,  4,
+#[cfg(feature = "audio")]
+fn audio_sink(samples_dir: &Path) -> Box<dyn AudioSink> {
+    use rust_8080::sound::{RodioSink, SampleSet};
 
-    11, 10, 10, 10, 17, 11, 7,  11, 11, 10, 10, 10, 17, 17, 7,  11,
-    11, 10, 10, 10, 17, 11, 7,  11, 11, 10, 10, 10, 17, 17, 7,  11,
-    11, 10, 10, 18, 17, 11, 7,  11, 11, 5,  10, 5,  17, 17, 7,  11,
-    11, 10, 10, 4,  17, 11, 7,  11, 11, 5,  10, 4,  17, 17, 7,  11
-];
+    let samples = if samples_dir.is_dir() {
+        let (samples, warnings) = SampleSet::load(samples_dir);
+        for warning in warnings {
+            eprintln!("{}", warning);
+        }
+        samples
+    } else {
+        SampleSet::synthesized()
+    };
+    match RodioSink::new(samples) {
+        Ok(sink) => Box::new(sink),
+        Err(e) => {
+            eprintln!("no audio output: {}", e);
+            Box::new(NullSink)
+        }
+    }
+}
 
-const SIZE: [u8; 256] = [
-    // x1 x2 x3 x4 x5 x6 x7 x8 x9 xA xB xC xD xE xF
-    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, //0x
-    1, 3, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1, //1x
-    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, //2x
-    1, 3, 3, 1, 1, 1, 2, 1, 1, 1, 3, 1, 1, 1, 2, 1, //3x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //4x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //5x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //6x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //7x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //8x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //9x
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //Ax
-    1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, //Bx
-    1, 1, 3, 3, 3, 1, 2, 1, 1, 1, 3, 3, 3, 3, 2, 1, //Cx
-    1, 1, 3, 2, 3, 1, 2, 1, 1, 1, 3, 2, 3, 3, 2, 1, //Dx
-    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, //Ex
-    1, 1, 3, 1, 3, 1, 2, 1, 1, 1, 3, 1, 3, 3, 2, 1, //Fx
-];
+#[cfg(not(feature = "audio"))]
+fn audio_sink(_samples_dir: &Path) -> Box<dyn AudioSink> {
+    Box::new(NullSink)
+}
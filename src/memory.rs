@@ -0,0 +1,236 @@
+use crate::savestate::{Input, Snapshot, StateError};
+
+/// Anything the 8080 can address through its 16 bit memory bus.
+pub trait Bus {
+    /// Reads a byte without any side effects, used by debuggers and the renderer.
+    fn peek(&self, addr: u16) -> u8;
+
+    /// Reads a byte on behalf of the CPU.
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8);
+
+    /// Called after every executed instruction with the number of cycles it took.
+    fn tick(&mut self, _cycles: u32) {}
+
+    /// An address the bus refused to access since the last call, which the CPU
+    /// reports as an error.
+    fn take_fault(&mut self) -> Option<u16> {
+        None
+    }
+}
+
+const PAGE_SIZE: usize = 0x100;
+const PAGES: usize = 0x100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Page {
+    Unmapped,
+    /// Writable page backed by the given physical page.
+    Ram(u8),
+    /// Read-only page backed by the given physical page.
+    Rom(u8),
+}
+
+/// What the CPU gets when it accesses an unmapped page.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UnmappedPolicy {
+    /// Reads return the unmapped value and writes are ignored.
+    #[default]
+    OpenBus,
+    /// The upper address lines are not decoded: the address is taken modulo the
+    /// smallest power of two covering every mapped page.
+    Mirror,
+    /// Like open bus, but the access is reported through `Bus::take_fault`.
+    Trap,
+}
+
+/// The stock memory bus: 64 KiB of physical storage mapped in 256 byte pages,
+/// with read-only ROM pages, mirrored pages and a configurable policy for unmapped pages.
+pub struct MemoryMap {
+    data: Vec<u8>,
+    pages: [Page; PAGES],
+    unmapped_value: u8,
+    policy: UnmappedPolicy,
+    fault: Option<u16>,
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMap {
+    /// Flat 64 KiB of RAM.
+    pub fn new() -> Self {
+        let mut map = Self::unmapped();
+        map.map_ram(0x0000, 0x10000);
+        map
+    }
+
+    /// Nothing mapped; every read returns the unmapped value.
+    pub fn unmapped() -> Self {
+        MemoryMap {
+            data: vec![0; 0x10000],
+            pages: [Page::Unmapped; PAGES],
+            unmapped_value: 0xff,
+            policy: UnmappedPolicy::OpenBus,
+            fault: None,
+        }
+    }
+
+    /// Space Invaders board: 8 KiB ROM, 1 KiB work RAM and 7 KiB video RAM, the
+    /// whole 16 KiB repeated across the address space because A14/A15 are not decoded.
+    pub fn space_invaders() -> Self {
+        let mut map = Self::unmapped();
+        map.map_rom(0x0000, 0x2000);
+        map.map_ram(0x2000, 0x2000);
+        map.mirror(0x4000, 0xc000, 0x0000, 0x4000);
+        map
+    }
+
+    pub fn set_unmapped_value(&mut self, value: u8) {
+        self.unmapped_value = value;
+    }
+
+    pub fn set_unmapped_policy(&mut self, policy: UnmappedPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn map_ram(&mut self, start: u16, len: usize) {
+        for page in page_range(start, len) {
+            self.pages[page] = Page::Ram(page as u8);
+        }
+    }
+
+    pub fn map_rom(&mut self, start: u16, len: usize) {
+        for page in page_range(start, len) {
+            self.pages[page] = Page::Rom(page as u8);
+        }
+    }
+
+    pub fn unmap(&mut self, start: u16, len: usize) {
+        for page in page_range(start, len) {
+            self.pages[page] = Page::Unmapped;
+        }
+    }
+
+    /// Makes `start..start + len` an alias of `source..source + source_len`, repeating the
+    /// source as often as needed. The source must already be mapped.
+    pub fn mirror(&mut self, start: u16, len: usize, source: u16, source_len: usize) {
+        let source: Vec<usize> = page_range(source, source_len).collect();
+        for (i, page) in page_range(start, len).enumerate() {
+            self.pages[page] = self.pages[source[i % source.len()]];
+        }
+    }
+
+    pub fn page(&self, addr: u16) -> Page {
+        self.pages[usize::from(addr) / PAGE_SIZE]
+    }
+
+    /// Copies `data` into physical memory at `addr`, ignoring write protection.
+    pub fn load(&mut self, addr: u16, data: &[u8]) {
+        for (i, byte) in data.iter().enumerate() {
+            let addr = addr.wrapping_add(i as u16);
+            if let Some(offset) = self.physical(addr) {
+                self.data[offset] = *byte;
+            }
+        }
+    }
+
+    fn physical(&self, addr: u16) -> Option<usize> {
+        let offset = usize::from(addr) % PAGE_SIZE;
+        match self.page(addr) {
+            Page::Unmapped => None,
+            Page::Ram(page) | Page::Rom(page) => Some(usize::from(page) * PAGE_SIZE + offset),
+        }
+    }
+
+    /// The mapped address an access to `addr` ends up at under the policy, `None`
+    /// when it reaches nothing.
+    fn decode(&self, addr: u16) -> Option<u16> {
+        if self.page(addr) != Page::Unmapped {
+            return Some(addr);
+        }
+        if self.policy != UnmappedPolicy::Mirror {
+            return None;
+        }
+        let last = self.pages.iter().rposition(|page| *page != Page::Unmapped)?;
+        let mask = ((last + 1) * PAGE_SIZE).next_power_of_two() - 1;
+        let mirrored = addr & mask as u16;
+        Some(mirrored).filter(|addr| self.page(*addr) != Page::Unmapped)
+    }
+}
+
+impl Bus for MemoryMap {
+    fn peek(&self, addr: u16) -> u8 {
+        match self.decode(addr).and_then(|addr| self.physical(addr)) {
+            Some(offset) => self.data[offset],
+            None => self.unmapped_value,
+        }
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        if self.policy == UnmappedPolicy::Trap && self.page(addr) == Page::Unmapped {
+            self.fault.get_or_insert(addr);
+        }
+        self.peek(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let Some(addr) = self.decode(addr) else {
+            if self.policy == UnmappedPolicy::Trap {
+                self.fault.get_or_insert(addr);
+            }
+            return;
+        };
+        if let (Page::Ram(_), Some(offset)) = (self.page(addr), self.physical(addr)) {
+            self.data[offset] = value;
+        }
+    }
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.fault.take()
+    }
+}
+
+fn page_range(start: u16, len: usize) -> impl Iterator<Item = usize> {
+    let first = usize::from(start) / PAGE_SIZE;
+    let count = len.div_ceil(PAGE_SIZE);
+    (first..first + count).map(|page| page % PAGES)
+}
+
+/// Contents, page mapping and the unmapped value.
+impl Snapshot for MemoryMap {
+    fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.data);
+        for page in &self.pages {
+            out.extend_from_slice(&match *page {
+                Page::Unmapped => [0, 0],
+                Page::Ram(physical) => [1, physical],
+                Page::Rom(physical) => [2, physical],
+            });
+        }
+        out.push(self.unmapped_value);
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        let data = input.bytes(self.data.len())?;
+        let mut pages = [Page::Unmapped; PAGES];
+        for page in pages.iter_mut() {
+            *page = match input.bytes(2)? {
+                [0, _] => Page::Unmapped,
+                [1, physical] => Page::Ram(*physical),
+                [2, physical] => Page::Rom(*physical),
+                _ => return Err(StateError::Corrupt),
+            };
+        }
+        self.data.copy_from_slice(data);
+        self.pages = pages;
+        self.unmapped_value = input.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,163 @@
+use crate::invaders::DipSwitches;
+use crate::savestate::{self, Input, Snapshot, StateError};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// First bytes of a movie file.
+pub const MAGIC: &[u8; 8] = b"8080MOVI";
+pub const VERSION: u16 = 1;
+
+#[derive(Debug)]
+pub enum MovieError {
+    Io { path: PathBuf, error: io::Error },
+    NotAMovie,
+    Version { found: u16 },
+    /// The movie was recorded with a different ROM set.
+    WrongRom { expected: u32, found: u32 },
+    Corrupt,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            MovieError::NotAMovie => write!(f, "not a movie"),
+            MovieError::Version { found } => write!(f, "movie version {} is not supported, expected {}", found, VERSION),
+            MovieError::WrongRom { expected, found } => write!(
+                f,
+                "movie was recorded with ROM set {:08x}, the loaded ROM set is {:08x}",
+                found, expected
+            ),
+            MovieError::Corrupt => write!(f, "movie file is damaged"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+impl From<StateError> for MovieError {
+    fn from(_: StateError) -> Self {
+        MovieError::Corrupt
+    }
+}
+
+/// Where a movie begins.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Start {
+    /// Reset with the ROMs loaded and these DIP switches.
+    PowerOn(DipSwitches),
+    /// A save state file, embedded whole.
+    State(Vec<u8>),
+}
+
+/// Input ports 1 and 2 for every frame from a known start, and a checksum of the
+/// machine state after the last frame to tell whether a replay stayed in sync.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_id: u32,
+    pub start: Start,
+    /// Button bits in effect during each frame.
+    pub frames: Vec<[u8; 2]>,
+    pub checksum: u32,
+}
+
+impl Movie {
+    pub fn new(rom_id: u32, start: Start) -> Self {
+        Movie {
+            rom_id,
+            start,
+            frames: Vec::new(),
+            checksum: 0,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&VERSION.to_le_bytes());
+        out.extend_from_slice(&self.rom_id.to_le_bytes());
+        match &self.start {
+            Start::PowerOn(dips) => {
+                out.push(0);
+                out.extend_from_slice(&[dips.lives, u8::from(dips.bonus_life_at_1000), u8::from(dips.coin_info)]);
+            }
+            Start::State(state) => {
+                out.push(1);
+                out.extend_from_slice(&(state.len() as u32).to_le_bytes());
+                out.extend_from_slice(state);
+            }
+        }
+        out.extend_from_slice(&(self.frames.len() as u32).to_le_bytes());
+        for frame in &self.frames {
+            out.extend_from_slice(frame);
+        }
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+        out
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, MovieError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let mut input = Input::new(&data[MAGIC.len()..]);
+        let version = input.u16()?;
+        if version != VERSION {
+            return Err(MovieError::Version { found: version });
+        }
+        let rom_id = input.u32()?;
+        let start = match input.u8()? {
+            0 => Start::PowerOn(DipSwitches {
+                lives: input.u8()?,
+                bonus_life_at_1000: input.bool()?,
+                coin_info: input.bool()?,
+            }),
+            1 => {
+                let len = input.u32()? as usize;
+                Start::State(input.bytes(len)?.to_vec())
+            }
+            _ => return Err(MovieError::NotAMovie),
+        };
+        let count = input.u32()? as usize;
+        let frames = input.bytes(count * 2)?.chunks(2).map(|frame| [frame[0], frame[1]]).collect();
+        let checksum = input.u32()?;
+        if !input.is_empty() {
+            return Err(MovieError::Corrupt);
+        }
+        Ok(Movie {
+            rom_id,
+            start,
+            frames,
+            checksum,
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), MovieError> {
+        fs::write(path, self.encode()).map_err(|error| MovieError::Io {
+            path: path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Reads a movie recorded with the ROM set `rom_id`.
+    pub fn load(path: &Path, rom_id: u32) -> Result<Self, MovieError> {
+        let data = fs::read(path).map_err(|error| MovieError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let movie = Movie::decode(&data)?;
+        if movie.rom_id != rom_id {
+            return Err(MovieError::WrongRom {
+                expected: rom_id,
+                found: movie.rom_id,
+            });
+        }
+        Ok(movie)
+    }
+}
+
+/// CRC-32 of the machine state, compared at the end of a replay.
+pub fn checksum(parts: &[&dyn Snapshot]) -> u32 {
+    crc32fast::hash(&savestate::payload(parts))
+}
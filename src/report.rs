@@ -0,0 +1,172 @@
+use crate::cpu::debugging::Instructioninfo;
+use crate::cpu::Cpu8080;
+use crate::debugger;
+use crate::io::IoBus;
+use crate::memory::Bus;
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+/// Stack words shown in a report, starting at SP.
+const STACK_WORDS: u16 = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// Hexdump with an ASCII column, PC and SP marked and repeated lines collapsed.
+    Hexdump,
+    IntelHex,
+    /// The bytes themselves, written next to the report with a `.bin` extension.
+    Raw,
+}
+
+/// Remembers the last instructions and writes reports of the machine state.
+pub struct Reporter {
+    format: DumpFormat,
+    range: RangeInclusive<u16>,
+    history: VecDeque<Instructioninfo>,
+    capacity: usize,
+    instructions: u64,
+}
+
+impl Reporter {
+    /// Keeps the last `capacity` instructions and dumps the whole address space.
+    pub fn new(format: DumpFormat, capacity: usize) -> Self {
+        Reporter {
+            format,
+            range: 0x0000..=0xffff,
+            history: VecDeque::with_capacity(capacity),
+            capacity,
+            instructions: 0,
+        }
+    }
+
+    pub fn set_range(&mut self, range: RangeInclusive<u16>) {
+        self.range = range;
+    }
+
+    /// Counts the instruction the CPU is about to execute and keeps it in the history.
+    pub fn record(&mut self, info: Instructioninfo) {
+        if self.capacity > 0 {
+            if self.history.len() == self.capacity {
+                self.history.pop_front();
+            }
+            self.history.push_back(info);
+        }
+        self.instructions += 1;
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// The recorded instructions, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &Instructioninfo> {
+        self.history.iter()
+    }
+
+    /// A file name for a report taken on request, numbered by the instruction count.
+    pub fn dump_path(&self) -> PathBuf {
+        PathBuf::from(format!("dump-{}.txt", self.instructions))
+    }
+
+    /// Writes a report explaining `reason` to `path`. Raw memory goes to a
+    /// `.bin` file next to it.
+    pub fn write<M: Bus, I: IoBus>(&self, cpu: &Cpu8080<M, I>, reason: &str, path: &Path) -> io::Result<()> {
+        let mut text = String::new();
+        let _ = writeln!(text, "reason: {}", reason);
+        let _ = writeln!(text, "instructions executed: {}", self.instructions);
+        let _ = writeln!(text, "\n{}", debugger::registers(cpu.state()));
+
+        let _ = writeln!(text, "\nlast {} instructions:", self.history.len());
+        for info in &self.history {
+            let _ = writeln!(text, "{}", info);
+        }
+
+        let sp = cpu.sp();
+        let _ = writeln!(text, "\nstack:");
+        for i in 0..STACK_WORDS {
+            let addr = sp.wrapping_add(i * 2);
+            let word = u16::from_le_bytes([cpu.memory().peek(addr), cpu.memory().peek(addr.wrapping_add(1))]);
+            let _ = writeln!(text, "{:04x}  {:04x}", addr, word);
+        }
+
+        let (start, end) = (*self.range.start(), *self.range.end());
+        let len = u32::from(end) - u32::from(start) + 1;
+        let _ = writeln!(text, "\nmemory {:04x}-{:04x}:", start, end);
+        match self.format {
+            DumpFormat::Hexdump => {
+                let marks = [(cpu.pc(), "PC"), (sp, "SP")];
+                text.push_str(&hexdump(cpu.memory(), start, len, &marks, true));
+            }
+            DumpFormat::IntelHex => text.push_str(&intel_hex(cpu.memory(), start, len)),
+            DumpFormat::Raw => {
+                let raw_path = path.with_extension("bin");
+                let bytes: Vec<u8> = (0..len).map(|i| cpu.memory().peek(start.wrapping_add(i as u16))).collect();
+                fs::write(&raw_path, bytes)?;
+                let _ = writeln!(text, "written to {}", raw_path.display());
+            }
+        }
+        fs::write(path, text)
+    }
+}
+
+/// 16 bytes per line with an ASCII column. Lines holding a marked address are
+/// labelled, and with `collapse` runs of identical lines are replaced by `*`.
+pub fn hexdump<M: Bus>(memory: &M, start: u16, len: u32, marks: &[(u16, &str)], collapse: bool) -> String {
+    let mut text = String::new();
+    let mut previous: Option<Vec<u8>> = None;
+    let mut skipping = false;
+    let mut offset: u32 = 0;
+    while offset < len {
+        let line_addr = start.wrapping_add(offset as u16);
+        let count = (len - offset).min(16) as u16;
+        let bytes: Vec<u8> = (0..count).map(|i| memory.peek(line_addr.wrapping_add(i))).collect();
+        let labels: Vec<&str> = marks
+            .iter()
+            .filter(|(addr, _)| addr.wrapping_sub(line_addr) < count)
+            .map(|(_, label)| *label)
+            .collect();
+        offset += 16;
+        if collapse && labels.is_empty() && previous.as_ref() == Some(&bytes) && offset < len {
+            if !skipping {
+                text.push_str("*\n");
+                skipping = true;
+            }
+            continue;
+        }
+        skipping = false;
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|b| if b.is_ascii_graphic() || *b == b' ' { *b as char } else { '.' })
+            .collect();
+        let _ = write!(text, "{:04x}  {:<47}  {}", line_addr, hex.join(" "), ascii);
+        if !labels.is_empty() {
+            let _ = write!(text, "{:width$}  <- {}", "", labels.join(", "), width = 16 - ascii.len());
+        }
+        text.push('\n');
+        previous = Some(bytes);
+    }
+    text
+}
+
+/// Intel HEX data records of 16 bytes and an end of file record.
+pub fn intel_hex<M: Bus>(memory: &M, start: u16, len: u32) -> String {
+    let mut text = String::new();
+    let mut offset: u32 = 0;
+    while offset < len {
+        let addr = start.wrapping_add(offset as u16);
+        let count = (len - offset).min(16) as u16;
+        let bytes: Vec<u8> = (0..count).map(|i| memory.peek(addr.wrapping_add(i))).collect();
+        let [high, low] = addr.to_be_bytes();
+        let sum = bytes.iter().fold((count as u8).wrapping_add(high).wrapping_add(low), |sum, b| sum.wrapping_add(*b));
+        let data: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let _ = writeln!(text, ":{:02X}{:04X}00{}{:02X}", count, addr, data, sum.wrapping_neg());
+        offset += 16;
+    }
+    text.push_str(":00000001FF\n");
+    text
+}
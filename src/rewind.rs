@@ -0,0 +1,211 @@
+use std::collections::VecDeque;
+
+/// Equal bytes between two changed runs that are still copied into one run,
+/// cheaper than the header of a new run.
+const MERGE_GAP: usize = 8;
+
+/// Turns a newer snapshot back into the one taken before it.
+enum Delta {
+    /// Runs of `skip: u32, len: u32, bytes` overwriting the newer snapshot.
+    Patch(Vec<u8>),
+    /// The snapshots differ in size.
+    Full(Vec<u8>),
+}
+
+impl Delta {
+    fn len(&self) -> usize {
+        match self {
+            Delta::Patch(patch) => patch.len(),
+            Delta::Full(snapshot) => snapshot.len(),
+        }
+    }
+}
+
+/// Ring buffer of snapshots taken every frame, bounded by the memory it uses.
+/// Only the newest snapshot is kept whole, older ones are stored as the changes
+/// that turn their successor back into them.
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    deltas: VecDeque<Delta>,
+    budget: usize,
+    used: usize,
+}
+
+impl Rewind {
+    /// Keeps as many snapshots as fit in `budget` bytes.
+    pub fn new(budget: usize) -> Self {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            budget,
+            used: 0,
+        }
+    }
+
+    /// Adds the newest snapshot, dropping the oldest ones over the budget.
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if let Some(latest) = self.latest.take() {
+            self.used -= latest.len();
+            let delta = if latest.len() == snapshot.len() {
+                Delta::Patch(diff(&snapshot, &latest))
+            } else {
+                Delta::Full(latest)
+            };
+            self.used += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.used += snapshot.len();
+        self.latest = Some(snapshot);
+        while self.used > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.used -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Steps back one snapshot and returns it, the newest one is discarded.
+    /// `None` once the oldest snapshot is reached.
+    pub fn pop(&mut self) -> Option<&[u8]> {
+        let delta = self.deltas.pop_back()?;
+        let latest = self.latest.as_mut().expect("deltas lead back from the latest snapshot");
+        self.used -= delta.len() + latest.len();
+        match delta {
+            Delta::Patch(patch) => apply(latest, &patch),
+            Delta::Full(snapshot) => *latest = snapshot,
+        }
+        self.used += latest.len();
+        Some(latest)
+    }
+
+    /// Number of snapshots held.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + usize::from(self.latest.is_some())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Bytes used by the snapshots.
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.used = 0;
+    }
+}
+
+/// The runs of `to` that differ from `from`, both of the same length.
+fn diff(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut patch = Vec::new();
+    let mut pos = 0;
+    while let Some(start) = (pos..to.len()).find(|&i| from[i] != to[i]) {
+        let mut end = start + 1;
+        while let Some(next) = (end..to.len().min(end + MERGE_GAP)).find(|&i| from[i] != to[i]) {
+            end = next + 1;
+        }
+        patch.extend_from_slice(&((start - pos) as u32).to_le_bytes());
+        patch.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        patch.extend_from_slice(&to[start..end]);
+        pos = end;
+    }
+    patch
+}
+
+fn apply(snapshot: &mut [u8], mut patch: &[u8]) {
+    let mut pos = 0;
+    while !patch.is_empty() {
+        let skip = u32::from_le_bytes(patch[0..4].try_into().unwrap()) as usize;
+        let len = u32::from_le_bytes(patch[4..8].try_into().unwrap()) as usize;
+        pos += skip;
+        snapshot[pos..pos + len].copy_from_slice(&patch[8..8 + len]);
+        pos += len;
+        patch = &patch[8 + len..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 1000 byte snapshot that differs from its neighbours in one byte.
+    fn snapshot(n: u8) -> Vec<u8> {
+        let mut snapshot = vec![0xaa; 1000];
+        snapshot[usize::from(n) * 7] = n;
+        snapshot
+    }
+
+    #[test]
+    fn pops_in_reverse_order() {
+        let mut rewind = Rewind::new(1 << 20);
+        assert!(rewind.pop().is_none());
+        for n in 0..10 {
+            rewind.push(snapshot(n));
+        }
+        assert_eq!(rewind.len(), 10);
+        for n in (0..9).rev() {
+            assert_eq!(rewind.pop(), Some(&snapshot(n)[..]));
+        }
+        assert!(rewind.pop().is_none());
+        assert_eq!((rewind.len(), rewind.used()), (1, 1000));
+    }
+
+    #[test]
+    fn full_snapshot_when_sizes_differ() {
+        let mut rewind = Rewind::new(1 << 20);
+        rewind.push(vec![1; 100]);
+        rewind.push(vec![2; 120]);
+        assert!(matches!(rewind.deltas[0], Delta::Full(_)));
+        assert_eq!(rewind.used(), 220);
+        assert_eq!(rewind.pop(), Some(&[1; 100][..]));
+        assert_eq!(rewind.used(), 100);
+    }
+
+    #[test]
+    fn evicts_oldest_within_budget() {
+        // neighbours differ in two bytes 7 apart, merged into one run of 8
+        const DELTA: usize = 8 + 8;
+        let mut rewind = Rewind::new(1000 + 5 * DELTA);
+        for n in 0..20 {
+            rewind.push(snapshot(n));
+            assert!(rewind.used() <= 1000 + 5 * DELTA);
+        }
+        assert_eq!((rewind.len(), rewind.used()), (6, 1000 + 5 * DELTA));
+        for n in (14..19).rev() {
+            assert_eq!(rewind.pop(), Some(&snapshot(n)[..]));
+            let deltas: usize = rewind.deltas.iter().map(Delta::len).sum();
+            assert_eq!(rewind.used(), 1000 + deltas);
+        }
+        assert!(rewind.pop().is_none());
+
+        rewind.clear();
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.used(), 0);
+    }
+
+    #[test]
+    fn merges_runs_across_small_gaps() {
+        let from = vec![0; 64];
+        let mut to = from.clone();
+        to[10] = 1;
+        to[10 + MERGE_GAP] = 2;
+        let patch = diff(&from, &to);
+        assert_eq!(patch.len(), 8 + MERGE_GAP + 1);
+        let mut patched = from.clone();
+        apply(&mut patched, &patch);
+        assert_eq!(patched, to);
+
+        to[10 + MERGE_GAP] = 0;
+        to[11 + MERGE_GAP] = 2;
+        let patch = diff(&from, &to);
+        assert_eq!(patch.len(), 2 * (8 + 1));
+        let mut patched = from;
+        apply(&mut patched, &patch);
+        assert_eq!(patched, to);
+        assert!(diff(&to, &to).is_empty());
+    }
+}
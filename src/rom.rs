@@ -0,0 +1,165 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// One file of a ROM set and where it goes in the address space.
+pub struct RomFile {
+    pub name: &'static str,
+    pub addr: u16,
+    pub len: usize,
+}
+
+/// The four 2 KiB ROMs of Space Invaders, as named in the MAME `invaders` set.
+pub const SPACE_INVADERS: [RomFile; 4] = [
+    RomFile { name: "invaders.h", addr: 0x0000, len: 0x800 },
+    RomFile { name: "invaders.g", addr: 0x0800, len: 0x800 },
+    RomFile { name: "invaders.f", addr: 0x1000, len: 0x800 },
+    RomFile { name: "invaders.e", addr: 0x1800, len: 0x800 },
+];
+
+#[derive(Debug)]
+pub enum RomError {
+    /// Files of the set that are not in the directory or archive.
+    Missing { source: PathBuf, files: Vec<&'static str> },
+    WrongSize { file: String, expected: usize, actual: usize },
+    Io { path: PathBuf, error: io::Error },
+    Zip { path: PathBuf, error: zip::result::ZipError },
+    /// The image does not fit between the load address and the end of memory.
+    TooLarge { path: PathBuf, addr: u16, len: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::Missing { source, files } => write!(
+                f,
+                "ROM set in {} is incomplete, missing: {}",
+                source.display(),
+                files.join(", ")
+            ),
+            RomError::WrongSize { file, expected, actual } => write!(
+                f,
+                "{} is {} bytes, expected {}",
+                file, actual, expected
+            ),
+            RomError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            RomError::Zip { path, error } => write!(f, "{}: {}", path.display(), error),
+            RomError::TooLarge { path, addr, len } => write!(
+                f,
+                "{} is {} bytes and does not fit at ${:04x}",
+                path.display(),
+                len,
+                addr
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// A loaded image and its load address.
+pub struct Rom {
+    pub addr: u16,
+    pub data: Vec<u8>,
+}
+
+/// Reads every file of `set` from the directory `dir`, reporting all missing files at once.
+pub fn load_set_from_dir(dir: &Path, set: &[RomFile]) -> Result<Vec<Rom>, RomError> {
+    let missing: Vec<&'static str> = set
+        .iter()
+        .filter(|file| !dir.join(file.name).is_file())
+        .map(|file| file.name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(RomError::Missing {
+            source: dir.to_path_buf(),
+            files: missing,
+        });
+    }
+    set.iter()
+        .map(|file| {
+            let path = dir.join(file.name);
+            let data = fs::read(&path).map_err(|error| RomError::Io { path, error })?;
+            to_rom(file, data)
+        })
+        .collect()
+}
+
+/// Reads every file of `set` from a zip archive. Names are matched case-insensitively
+/// and may sit in a subdirectory of the archive.
+pub fn load_set_from_zip(path: &Path, set: &[RomFile]) -> Result<Vec<Rom>, RomError> {
+    let zip_error = |error| RomError::Zip {
+        path: path.to_path_buf(),
+        error,
+    };
+    let file = File::open(path).map_err(|error| RomError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    let mut archive = zip::ZipArchive::new(file).map_err(zip_error)?;
+    let names: Vec<String> = archive.file_names().map(String::from).collect();
+    let find = |name: &str| {
+        names.iter().position(|entry| {
+            let base = entry.rsplit('/').next().unwrap_or(entry);
+            base.eq_ignore_ascii_case(name)
+        })
+    };
+    let missing: Vec<&'static str> = set.iter().filter(|f| find(f.name).is_none()).map(|f| f.name).collect();
+    if !missing.is_empty() {
+        return Err(RomError::Missing {
+            source: path.to_path_buf(),
+            files: missing,
+        });
+    }
+    let mut roms = Vec::new();
+    for rom_file in set {
+        let mut entry = archive.by_index(find(rom_file.name).unwrap()).map_err(zip_error)?;
+        let mut data = Vec::new();
+        entry.read_to_end(&mut data).map_err(|error| RomError::Io {
+            path: path.join(rom_file.name),
+            error,
+        })?;
+        roms.push(to_rom(rom_file, data)?);
+    }
+    Ok(roms)
+}
+
+/// Loads `set` from either a directory or a zip archive.
+pub fn load_set(path: &Path, set: &[RomFile]) -> Result<Vec<Rom>, RomError> {
+    if path.is_dir() {
+        load_set_from_dir(path, set)
+    } else {
+        load_set_from_zip(path, set)
+    }
+}
+
+/// Reads a single binary image that is loaded at `addr`.
+pub fn load_binary(path: &Path, addr: u16) -> Result<Rom, RomError> {
+    let data = fs::read(path).map_err(|error| RomError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    if usize::from(addr) + data.len() > 0x10000 {
+        return Err(RomError::TooLarge {
+            path: path.to_path_buf(),
+            addr,
+            len: data.len(),
+        });
+    }
+    Ok(Rom { addr, data })
+}
+
+fn to_rom(file: &RomFile, data: Vec<u8>) -> Result<Rom, RomError> {
+    if data.len() != file.len {
+        return Err(RomError::WrongSize {
+            file: file.name.to_string(),
+            expected: file.len,
+            actual: data.len(),
+        });
+    }
+    Ok(Rom {
+        addr: file.addr,
+        data,
+    })
+}
@@ -0,0 +1,275 @@
+use crate::rom::Rom;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// First bytes of a save state file.
+pub const MAGIC: &[u8; 8] = b"8080SAVE";
+/// Layout version of the payload, bumped whenever a `Snapshot` impl changes.
+pub const VERSION: u16 = 1;
+/// Magic, version, ROM id and payload length.
+const HEADER_LEN: usize = 18;
+
+#[derive(Debug)]
+pub enum StateError {
+    Io { path: PathBuf, error: io::Error },
+    NotAState,
+    Version { found: u16 },
+    Checksum,
+    /// The state was saved with a different ROM set.
+    WrongRom { expected: u32, found: u32 },
+    /// The payload does not match what the machine expects.
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::Version { found } => write!(f, "save state version {} is not supported, expected {}", found, VERSION),
+            StateError::Checksum => write!(f, "save state checksum mismatch, the file is damaged"),
+            StateError::WrongRom { expected, found } => write!(
+                f,
+                "save state was made with ROM set {:08x}, the loaded ROM set is {:08x}",
+                found, expected
+            ),
+            StateError::Corrupt => write!(f, "save state does not fit this machine"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// A part of the machine whose state goes into a save state.
+pub trait Snapshot {
+    fn save(&self, out: &mut Vec<u8>);
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError>;
+}
+
+/// Reads back the values a `Snapshot::save` wrote, in the same order.
+pub struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Input<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Input { data }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Corrupt);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+/// Identifies a ROM set by the CRC-32 of its load addresses and contents.
+pub fn rom_id(roms: &[Rom]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    for rom in roms {
+        hasher.update(&rom.addr.to_le_bytes());
+        hasher.update(&rom.data);
+    }
+    hasher.finalize()
+}
+
+/// The state of `parts`, in order.
+pub fn payload(parts: &[&dyn Snapshot]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for part in parts {
+        part.save(&mut out);
+    }
+    out
+}
+
+/// Restores `parts` from a `payload` of the same machine.
+pub fn restore_payload(payload: &[u8], parts: &mut [&mut dyn Snapshot]) -> Result<(), StateError> {
+    let mut input = Input::new(payload);
+    for part in parts.iter_mut() {
+        part.restore(&mut input)?;
+    }
+    if !input.is_empty() {
+        return Err(StateError::Corrupt);
+    }
+    Ok(())
+}
+
+/// A complete save state file: header, payload and a CRC-32 over both.
+pub fn encode(rom_id: u32, parts: &[&dyn Snapshot]) -> Vec<u8> {
+    let payload = payload(parts);
+    let mut out = Vec::with_capacity(HEADER_LEN + payload.len() + 4);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&rom_id.to_le_bytes());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(&payload);
+    let checksum = crc32fast::hash(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// Checks a save state file and restores `parts` from it. Damaged files and
+/// states of another ROM set are refused, leaving `parts` unchanged.
+pub fn decode(data: &[u8], rom_id: u32, parts: &mut [&mut dyn Snapshot]) -> Result<(), StateError> {
+    if data.len() < HEADER_LEN + 4 || &data[..8] != MAGIC {
+        return Err(StateError::NotAState);
+    }
+    let mut header = Input::new(&data[8..HEADER_LEN]);
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(StateError::Version { found: version });
+    }
+    let found = header.u32()?;
+    let len = header.u32()? as usize;
+    if data.len() != HEADER_LEN + len + 4 {
+        return Err(StateError::Checksum);
+    }
+    let (body, checksum) = data.split_at(HEADER_LEN + len);
+    if crc32fast::hash(body).to_le_bytes() != checksum {
+        return Err(StateError::Checksum);
+    }
+    if found != rom_id {
+        return Err(StateError::WrongRom { expected: rom_id, found });
+    }
+    let backup = payload(&parts.iter().map(|part| &**part).collect::<Vec<&dyn Snapshot>>());
+    match restore_payload(&body[HEADER_LEN..], parts) {
+        Err(error) => {
+            restore_payload(&backup, parts).expect("the machine restores its own state");
+            Err(error)
+        }
+        ok => ok,
+    }
+}
+
+pub fn save_file(path: &Path, rom_id: u32, parts: &[&dyn Snapshot]) -> Result<(), StateError> {
+    let io_error = |error| StateError::Io {
+        path: path.to_path_buf(),
+        error,
+    };
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    fs::write(path, encode(rom_id, parts)).map_err(io_error)
+}
+
+pub fn load_file(path: &Path, rom_id: u32, parts: &mut [&mut dyn Snapshot]) -> Result<(), StateError> {
+    let data = fs::read(path).map_err(|error| StateError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    decode(&data, rom_id, parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::Cpu8080;
+    use crate::invaders::{self, InvadersIo};
+    use crate::memory::{Bus, MemoryMap};
+    use crate::timing::Scheduler;
+
+    const ROM_ID: u32 = 0x1234_5678;
+
+    fn machine() -> (Cpu8080<MemoryMap, InvadersIo>, Scheduler) {
+        let mut cpu = Cpu8080::with_devices(MemoryMap::new(), InvadersIo::new());
+        // LXI SP,$2400 ; EI ; INR A ; STA $2000 ; JMP $0004
+        cpu.memory_mut().load(0, &[0x31, 0x00, 0x24, 0xfb, 0x3c, 0x32, 0x00, 0x20, 0xc3, 0x04, 0x00]);
+        (cpu, invaders::scheduler())
+    }
+
+    fn run(cpu: &mut Cpu8080<MemoryMap, InvadersIo>, scheduler: &mut Scheduler, steps: usize) {
+        for _ in 0..steps {
+            scheduler.step(cpu).unwrap();
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let (mut cpu, mut scheduler) = machine();
+        run(&mut cpu, &mut scheduler, 5000);
+        let saved = payload(&[&cpu, &scheduler]);
+        let data = encode(ROM_ID, &[&cpu, &scheduler]);
+
+        run(&mut cpu, &mut scheduler, 5000);
+        assert_ne!(payload(&[&cpu, &scheduler]), saved);
+        decode(&data, ROM_ID, &mut [&mut cpu, &mut scheduler]).unwrap();
+        assert_eq!(payload(&[&cpu, &scheduler]), saved);
+        assert_eq!(encode(ROM_ID, &[&cpu, &scheduler]), data);
+    }
+
+    #[test]
+    fn refuses_damaged_and_foreign_states() {
+        let (mut cpu, mut scheduler) = machine();
+        run(&mut cpu, &mut scheduler, 100);
+        let data = encode(ROM_ID, &[&cpu, &scheduler]);
+        let before = payload(&[&cpu, &scheduler]);
+        let mut decode_into = |data: &[u8], rom_id| decode(data, rom_id, &mut [&mut cpu, &mut scheduler]);
+
+        assert!(matches!(
+            decode_into(&data, ROM_ID + 1),
+            Err(StateError::WrongRom { expected, found: ROM_ID }) if expected == ROM_ID + 1
+        ));
+
+        let mut flipped = data.clone();
+        flipped[HEADER_LEN + 100] ^= 0x01;
+        assert!(matches!(decode_into(&flipped, ROM_ID), Err(StateError::Checksum)));
+
+        let mut newer = data.clone();
+        newer[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(decode_into(&newer, ROM_ID), Err(StateError::Version { found }) if found == VERSION + 1));
+
+        assert!(matches!(decode_into(&data[..HEADER_LEN], ROM_ID), Err(StateError::NotAState)));
+        assert!(matches!(decode_into(&data[..data.len() - 1], ROM_ID), Err(StateError::Checksum)));
+        assert_eq!(payload(&[&cpu, &scheduler]), before);
+    }
+
+    #[test]
+    fn failed_restore_rolls_back() {
+        let (mut cpu, mut scheduler) = machine();
+        run(&mut cpu, &mut scheduler, 1000);
+        let data = encode(ROM_ID, &[&cpu, &scheduler]);
+
+        // the CPU restores, then the scheduler refuses a different frame length
+        let (mut other, _) = machine();
+        other.memory_mut().write(0x2100, 0x99);
+        let mut other_scheduler = Scheduler::new(1000, Vec::new());
+        let before = payload(&[&other, &other_scheduler]);
+        let result = decode(&data, ROM_ID, &mut [&mut other, &mut other_scheduler]);
+        assert!(matches!(result, Err(StateError::Corrupt)));
+        assert_eq!(payload(&[&other, &other_scheduler]), before);
+        assert_eq!(other.memory().peek(0x2100), 0x99);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::memory::Bus;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+/// The monitor is mounted rotated, so the picture is 224 pixels wide and 256 high.
+pub const WIDTH: usize = 224;
+pub const HEIGHT: usize = 256;
+pub const NUM_PIXELS: usize = WIDTH * HEIGHT;
+/// Video RAM, one bit per pixel, 32 bytes per column starting at the bottom.
+pub const VRAM_START: u16 = 0x2400;
+pub const VRAM_END: u16 = 0x3fff;
+
+/// The picture in video RAM as `0x00RRGGBB` pixels, row by row from the top.
+pub fn render<M: Bus>(memory: &M) -> Vec<u32> {
+    let mut buffer: Vec<u32> = vec![0; NUM_PIXELS];
+    let mut j = 0;
+    for row in (VRAM_START..VRAM_START + 0x20).rev() {
+        for b in (0..=7).rev() {
+            for col in 0..WIDTH as u16 {
+                let offset = row + (col * 0x20);
+                if (memory.peek(offset) & (0x1 << b)) != 0x0 {
+                    buffer[j] = 0x00ffffff;
+                } else {
+                    buffer[j] = 0x00000000;
+                }
+                j += 1;
+            }
+        }
+    }
+    buffer
+}
+
+/// Saves a rendered picture as an 8 bit RGB PNG.
+pub fn write_png(path: &Path, pixels: &[u32]) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = pixels.iter().flat_map(|pixel| (*pixel).to_be_bytes()[1..].to_vec()).collect();
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer.write_image_data(&data).map_err(io::Error::other)?;
+    writer.finish().map_err(io::Error::other)
+}
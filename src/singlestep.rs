@@ -0,0 +1,285 @@
+use crate::cpu::Cpu8080;
+use crate::disasm;
+use crate::io::IoBus;
+use crate::memory::{Bus, MemoryMap};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Failed tests listed per opcode in a report.
+const EXAMPLES: usize = 5;
+
+#[derive(Debug)]
+pub enum LoadError {
+    Io { path: PathBuf, error: io::Error },
+    Json { path: PathBuf, error: serde_json::Error },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            LoadError::Json { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// Registers, flags and the memory a test cares about.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Machine {
+    pub pc: u16,
+    pub sp: u16,
+    pub a: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    /// Flags as pushed by PUSH PSW.
+    pub f: u8,
+    pub h: u8,
+    pub l: u8,
+    #[serde(default)]
+    pub ram: Vec<(u16, u8)>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum Cycles {
+    Count(u32),
+    /// One entry per clock cycle with the bus activity, only the number is checked.
+    Bus(Vec<serde_json::Value>),
+}
+
+/// One instruction executed from a known state.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TestCase {
+    pub name: String,
+    pub initial: Machine,
+    #[serde(rename = "final")]
+    pub expected: Machine,
+    #[serde(default)]
+    cycles: Option<Cycles>,
+    /// `[port, value, "r" or "w"]` for IN and OUT.
+    #[serde(default)]
+    pub ports: Vec<(u16, u8, String)>,
+}
+
+impl TestCase {
+    pub fn cycles(&self) -> Option<u32> {
+        match &self.cycles {
+            Some(Cycles::Count(count)) => Some(*count),
+            Some(Cycles::Bus(cycles)) => Some(cycles.len() as u32),
+            None => None,
+        }
+    }
+
+    /// The instruction bytes at the initial PC.
+    pub fn instruction(&self) -> [u8; 3] {
+        let byte = |offset: u16| {
+            let addr = self.initial.pc.wrapping_add(offset);
+            self.initial.ram.iter().find(|(a, _)| *a == addr).map_or(0, |(_, value)| *value)
+        };
+        [byte(0), byte(1), byte(2)]
+    }
+}
+
+/// A field whose value after the instruction is not the expected one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub expected: String,
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: expected {}, found {}", self.field, self.expected, self.found)
+    }
+}
+
+/// Reads a JSON array of test cases.
+pub fn load(path: &Path) -> Result<Vec<TestCase>, LoadError> {
+    let text = fs::read_to_string(path).map_err(|error| LoadError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    serde_json::from_str(&text).map_err(|error| LoadError::Json {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Port reads served from a test and the writes seen.
+struct Ports {
+    reads: Vec<(u8, u8)>,
+    writes: Vec<(u8, u8)>,
+}
+
+impl IoBus for Ports {
+    fn input(&mut self, port: u8) -> u8 {
+        self.reads.iter().find(|(p, _)| *p == port).map_or(0xff, |(_, value)| *value)
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        self.writes.push((port, value));
+    }
+}
+
+/// Executes the instruction of `case` and returns every field that differs.
+pub fn run(case: &TestCase) -> Vec<Mismatch> {
+    let port_list = |kind: &str| -> Vec<(u8, u8)> {
+        case.ports
+            .iter()
+            .filter(|(_, _, k)| k == kind)
+            .map(|(port, value, _)| (*port as u8, *value))
+            .collect()
+    };
+    let ports = Ports {
+        reads: port_list("r"),
+        writes: Vec::new(),
+    };
+    let mut cpu = Cpu8080::with_devices(MemoryMap::new(), ports);
+    let initial = &case.initial;
+    {
+        let state = cpu.state_mut();
+        [state.a, state.b, state.c, state.d, state.e, state.h, state.l] =
+            [initial.a, initial.b, initial.c, initial.d, initial.e, initial.h, initial.l];
+        state.cc.set_psw(initial.f);
+        state.sp = initial.sp;
+        state.pc = initial.pc;
+    }
+    for (addr, value) in &initial.ram {
+        cpu.memory_mut().write(*addr, *value);
+    }
+
+    let mut mismatches = Vec::new();
+    let mut check = |field: &str, expected: String, found: String| {
+        if expected != found {
+            mismatches.push(Mismatch {
+                field: field.to_string(),
+                expected,
+                found,
+            });
+        }
+    };
+    match cpu.step() {
+        Ok(found) => {
+            if let Some(expected) = case.cycles() {
+                check("cycles", expected.to_string(), found.to_string());
+            }
+        }
+        Err(error) => check("step", "ok".to_string(), error.to_string()),
+    }
+    let expected = &case.expected;
+    let state = cpu.state();
+    check("pc", format!("{:04x}", expected.pc), format!("{:04x}", state.pc));
+    check("sp", format!("{:04x}", expected.sp), format!("{:04x}", state.sp));
+    for (name, expected, found) in [
+        ("a", expected.a, state.a),
+        ("b", expected.b, state.b),
+        ("c", expected.c, state.c),
+        ("d", expected.d, state.d),
+        ("e", expected.e, state.e),
+        ("h", expected.h, state.h),
+        ("l", expected.l, state.l),
+    ] {
+        check(name, format!("{:02x}", expected), format!("{:02x}", found));
+    }
+    check("f", flags(expected.f), flags(state.cc.psw()));
+    for (addr, value) in &expected.ram {
+        let found = cpu.memory().peek(*addr);
+        check(&format!("ram[{:04x}]", addr), format!("{:02x}", value), format!("{:02x}", found));
+    }
+    let writes = |writes: &[(u8, u8)]| {
+        let writes: Vec<String> = writes.iter().map(|(port, value)| format!("{:02x}<-{:02x}", port, value)).collect();
+        writes.join(" ")
+    };
+    check("out", writes(&port_list("w")), writes(&cpu.io().writes));
+    mismatches
+}
+
+/// Flags byte with the set flags spelled out, as in `f=d5 SZAPC`.
+fn flags(f: u8) -> String {
+    let names: String = [(0x80, 'S'), (0x40, 'Z'), (0x10, 'A'), (0x04, 'P'), (0x01, 'C')]
+        .iter()
+        .map(|(bit, name)| if f & bit != 0 { *name } else { '-' })
+        .collect();
+    format!("{:02x} {}", f, names)
+}
+
+#[derive(Default)]
+struct OpcodeResults {
+    run: usize,
+    failed: usize,
+    /// Name, instruction and mismatches of the first failures.
+    examples: Vec<(String, String, Vec<Mismatch>)>,
+}
+
+/// Results of many test cases grouped by opcode.
+#[derive(Default)]
+pub struct Report {
+    opcodes: BTreeMap<u8, OpcodeResults>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `case` and records the outcome, returning whether it passed.
+    pub fn run(&mut self, case: &TestCase) -> bool {
+        let mismatches = run(case);
+        let bytes = case.instruction();
+        let results = self.opcodes.entry(bytes[0]).or_default();
+        results.run += 1;
+        if mismatches.is_empty() {
+            return true;
+        }
+        results.failed += 1;
+        if results.examples.len() < EXAMPLES {
+            let text = disasm::decode(&bytes).unwrap_or_default();
+            results.examples.push((case.name.clone(), text, mismatches));
+        }
+        false
+    }
+
+    pub fn tests(&self) -> usize {
+        self.opcodes.values().map(|results| results.run).sum()
+    }
+
+    pub fn failures(&self) -> usize {
+        self.opcodes.values().map(|results| results.failed).sum()
+    }
+
+    /// Opcodes with at least one failed test.
+    pub fn failed_opcodes(&self) -> Vec<u8> {
+        self.opcodes.iter().filter(|(_, results)| results.failed > 0).map(|(opcode, _)| *opcode).collect()
+    }
+}
+
+/// Lists the failed opcodes with their first failures, then a summary.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (opcode, results) in self.opcodes.iter().filter(|(_, results)| results.failed > 0) {
+            writeln!(f, "opcode {:02x}: {} of {} failed", opcode, results.failed, results.run)?;
+            for (name, text, mismatches) in &results.examples {
+                writeln!(f, "  {} ({})", name, text)?;
+                for mismatch in mismatches {
+                    writeln!(f, "    {}", mismatch)?;
+                }
+            }
+        }
+        write!(
+            f,
+            "{} tests, {} failed in {} opcodes",
+            self.tests(),
+            self.failures(),
+            self.failed_opcodes().len()
+        )
+    }
+}
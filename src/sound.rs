@@ -0,0 +1,432 @@
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+
+/// Sound effects triggered through the Space Invaders sound latches on ports 3 and 5.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    Ufo,
+    Shot,
+    PlayerDeath,
+    InvaderHit,
+    Fleet1,
+    Fleet2,
+    Fleet3,
+    Fleet4,
+    UfoHit,
+    ExtendedPlay,
+}
+
+impl Sound {
+    /// Ordered by the number of the sample in the standard 0.wav-9.wav set.
+    pub const ALL: [Sound; 10] = [
+        Sound::Ufo,
+        Sound::Shot,
+        Sound::PlayerDeath,
+        Sound::InvaderHit,
+        Sound::Fleet1,
+        Sound::Fleet2,
+        Sound::Fleet3,
+        Sound::Fleet4,
+        Sound::UfoHit,
+        Sound::ExtendedPlay,
+    ];
+
+    pub fn sample_number(&self) -> usize {
+        Sound::ALL.iter().position(|s| s == self).unwrap()
+    }
+
+    /// The UFO sound repeats for as long as its latch bit is held.
+    pub fn looping(&self) -> bool {
+        *self == Sound::Ufo
+    }
+
+    /// Latch port and bit the sound is triggered by.
+    fn wiring(&self) -> (u8, u8) {
+        match self {
+            Sound::Ufo => (3, 0),
+            Sound::Shot => (3, 1),
+            Sound::PlayerDeath => (3, 2),
+            Sound::InvaderHit => (3, 3),
+            Sound::ExtendedPlay => (3, 4),
+            Sound::Fleet1 => (5, 0),
+            Sound::Fleet2 => (5, 1),
+            Sound::Fleet3 => (5, 2),
+            Sound::Fleet4 => (5, 3),
+            Sound::UfoHit => (5, 4),
+        }
+    }
+}
+
+/// Receives the sound triggers decoded from the latches.
+pub trait AudioSink {
+    fn play(&mut self, sound: Sound);
+
+    /// Called when the latch bit of a looping sound is released.
+    fn stop(&mut self, sound: Sound);
+}
+
+/// Discards every sound, for headless runs and machines without a sound device.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&mut self, _sound: Sound) {}
+
+    fn stop(&mut self, _sound: Sound) {}
+}
+
+/// Edge detector for the sound latches on ports 3 and 5.
+pub struct SoundLatches {
+    port3: u8,
+    port5: u8,
+    sink: Box<dyn AudioSink>,
+}
+
+impl Default for SoundLatches {
+    fn default() -> Self {
+        Self::new(Box::new(NullSink))
+    }
+}
+
+impl SoundLatches {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        SoundLatches {
+            port3: 0,
+            port5: 0,
+            sink,
+        }
+    }
+
+    pub fn set_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sink = sink;
+    }
+
+    /// Latches a value written to port 3 or 5, playing every sound whose bit went
+    /// from 0 to 1 and stopping looping sounds whose bit went from 1 to 0.
+    pub fn write(&mut self, port: u8, value: u8) {
+        let previous = match port {
+            3 => std::mem::replace(&mut self.port3, value),
+            5 => std::mem::replace(&mut self.port5, value),
+            _ => return,
+        };
+        for sound in Sound::ALL {
+            let (sound_port, bit) = sound.wiring();
+            if sound_port != port {
+                continue;
+            }
+            let was = previous & (1 << bit) != 0;
+            let is = value & (1 << bit) != 0;
+            if is && !was {
+                self.sink.play(sound);
+            } else if was && !is && sound.looping() {
+                self.sink.stop(sound);
+            }
+        }
+    }
+
+    pub fn latches(&self) -> (u8, u8) {
+        (self.port3, self.port5)
+    }
+
+    /// Sets both latches without triggering one-shot sounds, only looping sounds
+    /// are started or stopped to match.
+    pub fn restore_latches(&mut self, port3: u8, port5: u8) {
+        for sound in Sound::ALL.into_iter().filter(|sound| sound.looping()) {
+            let (port, bit) = sound.wiring();
+            let (was, is) = match port {
+                3 => (self.port3, port3),
+                _ => (self.port5, port5),
+            };
+            let (was, is) = (was & (1 << bit) != 0, is & (1 << bit) != 0);
+            if is && !was {
+                self.sink.play(sound);
+            } else if was && !is {
+                self.sink.stop(sound);
+            }
+        }
+        self.port3 = port3;
+        self.port5 = port5;
+    }
+}
+
+/// Mono 16 bit PCM.
+#[derive(Clone, Debug)]
+pub struct Sample {
+    pub rate: u32,
+    pub data: Vec<i16>,
+}
+
+const SYNTH_RATE: u32 = 22050;
+
+impl Sample {
+    /// Decodes an 8 or 16 bit PCM WAV file, mixing multiple channels down to mono.
+    pub fn from_wav(bytes: &[u8]) -> Result<Sample, String> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err("not a RIFF WAVE file".to_string());
+        }
+        let mut format: Option<(u16, u16, u32, u16)> = None;
+        let mut chunks = &bytes[12..];
+        while chunks.len() >= 8 {
+            let id = &chunks[0..4];
+            let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+            let body = chunks.get(8..8 + len).ok_or("truncated chunk")?;
+            match id {
+                b"fmt " if len >= 16 => {
+                    let tag = u16::from_le_bytes([body[0], body[1]]);
+                    let channels = u16::from_le_bytes([body[2], body[3]]);
+                    let rate = u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                    let bits = u16::from_le_bytes([body[14], body[15]]);
+                    format = Some((tag, channels, rate, bits));
+                }
+                b"data" => {
+                    let (tag, channels, rate, bits) = format.ok_or("data chunk before fmt chunk")?;
+                    if tag != 1 || channels == 0 {
+                        return Err(format!("unsupported format tag {}", tag));
+                    }
+                    let frames: Vec<i32> = match bits {
+                        8 => body.iter().map(|b| (i32::from(*b) - 128) << 8).collect(),
+                        16 => body
+                            .chunks_exact(2)
+                            .map(|b| i32::from(i16::from_le_bytes([b[0], b[1]])))
+                            .collect(),
+                        _ => return Err(format!("unsupported sample size {} bits", bits)),
+                    };
+                    let data = frames
+                        .chunks_exact(usize::from(channels))
+                        .map(|frame| (frame.iter().sum::<i32>() / frame.len() as i32) as i16)
+                        .collect();
+                    return Ok(Sample { rate, data });
+                }
+                _ => {}
+            }
+            // chunks are padded to an even length
+            chunks = chunks.get(8 + len + (len & 1)..).unwrap_or(&[]);
+        }
+        Err("no data chunk".to_string())
+    }
+
+    /// A rough stand-in for the analog sound circuit of `sound`.
+    pub fn synthesize(sound: Sound) -> Sample {
+        let tone = |seconds: f32, frequency: &dyn Fn(f32) -> f32, square: bool| {
+            let len = (seconds * SYNTH_RATE as f32) as usize;
+            let mut phase = 0.0f32;
+            (0..len)
+                .map(|i| {
+                    let t = i as f32 / SYNTH_RATE as f32;
+                    phase += frequency(t) / SYNTH_RATE as f32;
+                    let wave = if square {
+                        if phase.fract() < 0.5 { 1.0 } else { -1.0 }
+                    } else {
+                        (phase * 2.0 * PI).sin()
+                    };
+                    let envelope = 1.0 - t / seconds;
+                    (wave * envelope * 8000.0) as i16
+                })
+                .collect::<Vec<i16>>()
+        };
+        let mut noise_state: u32 = 0x1234_5678;
+        let mut noise = |seconds: f32| {
+            let len = (seconds * SYNTH_RATE as f32) as usize;
+            (0..len)
+                .map(|i| {
+                    noise_state ^= noise_state << 13;
+                    noise_state ^= noise_state >> 17;
+                    noise_state ^= noise_state << 5;
+                    let envelope = 1.0 - i as f32 / len as f32;
+                    ((noise_state >> 16) as i16 as f32 * envelope * 0.25) as i16
+                })
+                .collect::<Vec<i16>>()
+        };
+        let data = match sound {
+            Sound::Ufo => tone(0.2, &|t| 600.0 + 200.0 * (t * 10.0 * PI).sin(), false),
+            Sound::Shot => tone(0.3, &|t| 1200.0 - 3000.0 * t, true),
+            Sound::PlayerDeath => noise(1.0),
+            Sound::InvaderHit => noise(0.3),
+            Sound::Fleet1 => tone(0.1, &|_| 100.0, true),
+            Sound::Fleet2 => tone(0.1, &|_| 90.0, true),
+            Sound::Fleet3 => tone(0.1, &|_| 80.0, true),
+            Sound::Fleet4 => tone(0.1, &|_| 70.0, true),
+            Sound::UfoHit => tone(0.8, &|t| 300.0 + 200.0 * (t * 30.0 * PI).sin(), true),
+            Sound::ExtendedPlay => tone(0.6, &|t| 800.0 + 400.0 * (t * 8.0 * PI).sin(), false),
+        };
+        Sample {
+            rate: SYNTH_RATE,
+            data,
+        }
+    }
+}
+
+/// One sample per sound, indexed like `Sound::ALL`.
+pub struct SampleSet {
+    samples: Vec<Sample>,
+}
+
+impl SampleSet {
+    /// Synthesized samples only.
+    pub fn synthesized() -> Self {
+        SampleSet {
+            samples: Sound::ALL.iter().map(|s| Sample::synthesize(*s)).collect(),
+        }
+    }
+
+    /// Loads 0.wav-9.wav from `dir`, synthesizing every sample that is missing or unreadable.
+    /// Returns the set together with a warning for every sample that was replaced.
+    pub fn load(dir: &Path) -> (Self, Vec<String>) {
+        let mut warnings = Vec::new();
+        let samples = Sound::ALL
+            .iter()
+            .map(|sound| {
+                let path = dir.join(format!("{}.wav", sound.sample_number()));
+                let sample = fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|bytes| Sample::from_wav(&bytes));
+                sample.unwrap_or_else(|e| {
+                    warnings.push(format!("{}: {}, using synthesized {:?}", path.display(), e, sound));
+                    Sample::synthesize(*sound)
+                })
+            })
+            .collect();
+        (SampleSet { samples }, warnings)
+    }
+
+    pub fn get(&self, sound: Sound) -> &Sample {
+        &self.samples[sound.sample_number()]
+    }
+}
+
+#[cfg(feature = "audio")]
+pub use self::rodio_sink::RodioSink;
+
+#[cfg(feature = "audio")]
+mod rodio_sink {
+    use super::{AudioSink, SampleSet, Sound};
+    use rodio::buffer::SamplesBuffer;
+    use rodio::{OutputStream, OutputStreamHandle, Sink, Source};
+
+    /// Plays the samples on the default output device.
+    pub struct RodioSink {
+        // the stream stops playing when dropped
+        _stream: OutputStream,
+        handle: OutputStreamHandle,
+        samples: SampleSet,
+        loops: Vec<(Sound, Sink)>,
+    }
+
+    impl RodioSink {
+        pub fn new(samples: SampleSet) -> Result<Self, String> {
+            let (stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+            Ok(RodioSink {
+                _stream: stream,
+                handle,
+                samples,
+                loops: Vec::new(),
+            })
+        }
+
+        fn buffer(&self, sound: Sound) -> SamplesBuffer<i16> {
+            let sample = self.samples.get(sound);
+            SamplesBuffer::new(1, sample.rate, sample.data.clone())
+        }
+    }
+
+    impl AudioSink for RodioSink {
+        fn play(&mut self, sound: Sound) {
+            if sound.looping() {
+                if let Ok(sink) = Sink::try_new(&self.handle) {
+                    sink.append(self.buffer(sound).repeat_infinite());
+                    self.loops.push((sound, sink));
+                }
+            } else {
+                let _ = self.handle.play_raw(self.buffer(sound).convert_samples());
+            }
+        }
+
+        fn stop(&mut self, sound: Sound) {
+            self.loops.retain(|(s, sink)| {
+                if *s == sound {
+                    sink.stop();
+                }
+                *s != sound
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Event {
+        Play(Sound),
+        Stop(Sound),
+    }
+
+    /// Keeps every call for the test to inspect.
+    struct Recording(Rc<RefCell<Vec<Event>>>);
+
+    impl AudioSink for Recording {
+        fn play(&mut self, sound: Sound) {
+            self.0.borrow_mut().push(Event::Play(sound));
+        }
+
+        fn stop(&mut self, sound: Sound) {
+            self.0.borrow_mut().push(Event::Stop(sound));
+        }
+    }
+
+    fn latches() -> (SoundLatches, Rc<RefCell<Vec<Event>>>) {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        (SoundLatches::new(Box::new(Recording(events.clone()))), events)
+    }
+
+    fn take(events: &Rc<RefCell<Vec<Event>>>) -> Vec<Event> {
+        std::mem::take(&mut *events.borrow_mut())
+    }
+
+    #[test]
+    fn one_shots_on_rising_edges() {
+        let (mut latches, events) = latches();
+        latches.write(3, 0b0000_0010);
+        assert_eq!(take(&events), [Event::Play(Sound::Shot)]);
+        // held high: no re-trigger, falling edge: no stop for a one-shot
+        latches.write(3, 0b0000_0010);
+        latches.write(3, 0b0000_0000);
+        assert_eq!(take(&events), []);
+        latches.write(3, 0b0000_0010);
+        assert_eq!(take(&events), [Event::Play(Sound::Shot)]);
+
+        latches.write(5, 0b0001_0001);
+        assert_eq!(take(&events), [Event::Play(Sound::Fleet1), Event::Play(Sound::UfoHit)]);
+        // other ports are no latches
+        latches.write(6, 0xff);
+        assert_eq!(take(&events), []);
+        assert_eq!(latches.latches(), (0b0000_0010, 0b0001_0001));
+    }
+
+    #[test]
+    fn ufo_loops_while_held() {
+        let (mut latches, events) = latches();
+        latches.write(3, 0b0000_0001);
+        latches.write(3, 0b0000_0011);
+        latches.write(3, 0b0000_0001);
+        assert_eq!(take(&events), [Event::Play(Sound::Ufo), Event::Play(Sound::Shot)]);
+        latches.write(3, 0b0000_0000);
+        assert_eq!(take(&events), [Event::Stop(Sound::Ufo)]);
+    }
+
+    #[test]
+    fn restoring_latches_only_switches_loops() {
+        let (mut latches, events) = latches();
+        latches.restore_latches(0b0001_1111, 0b0001_1111);
+        assert_eq!(take(&events), [Event::Play(Sound::Ufo)]);
+        latches.restore_latches(0b0001_1110, 0);
+        assert_eq!(take(&events), [Event::Stop(Sound::Ufo)]);
+        assert_eq!(latches.latches(), (0b0001_1110, 0));
+        // edges are taken from the restored values
+        latches.write(3, 0b0001_1111);
+        assert_eq!(take(&events), [Event::Play(Sound::Ufo)]);
+    }
+}
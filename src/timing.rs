@@ -0,0 +1,154 @@
+use crate::cpu::{Cpu8080, EmulationError};
+use crate::io::IoBus;
+use crate::memory::Bus;
+use crate::savestate::{Input, Snapshot, StateError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Counts T-states within a video frame and raises interrupts at fixed points of it.
+pub struct Scheduler {
+    cycles_per_frame: u32,
+    /// (cycle within the frame, RST number), sorted by cycle
+    interrupts: Vec<(u32, u8)>,
+    frame_cycle: u32,
+    next_interrupt: usize,
+    frame_done: bool,
+    total_cycles: u64,
+    frames: u64,
+}
+
+impl Scheduler {
+    pub fn new(cycles_per_frame: u32, mut interrupts: Vec<(u32, u8)>) -> Self {
+        interrupts.sort();
+        Scheduler {
+            cycles_per_frame,
+            interrupts,
+            frame_cycle: 0,
+            next_interrupt: 0,
+            frame_done: false,
+            total_cycles: 0,
+            frames: 0,
+        }
+    }
+
+    /// Executes one instruction and raises every interrupt whose point in the frame
+    /// has been reached. Returns the cycles taken. The cycles of an instruction that
+    /// ran before failing are counted as well, so the frame keeps its timing when
+    /// emulation goes on after the error.
+    pub fn step<M: Bus, I: IoBus>(&mut self, cpu: &mut Cpu8080<M, I>) -> Result<u32, EmulationError> {
+        let result = cpu.step();
+        let cycles = match &result {
+            Ok(cycles) => *cycles,
+            Err(error) => error.cycles(),
+        };
+        self.total_cycles += u64::from(cycles);
+        self.frame_cycle += cycles;
+        while let Some((at, rst)) = self.interrupts.get(self.next_interrupt) {
+            if self.frame_cycle < *at {
+                break;
+            }
+            cpu.interrupt(*rst);
+            self.next_interrupt += 1;
+        }
+        if self.frame_cycle >= self.cycles_per_frame {
+            self.frame_cycle -= self.cycles_per_frame;
+            self.next_interrupt = 0;
+            self.frame_done = true;
+            self.frames += 1;
+        }
+        result
+    }
+
+    /// Runs until the end of the current frame and returns the number of instructions executed.
+    pub fn run_frame<M: Bus, I: IoBus>(&mut self, cpu: &mut Cpu8080<M, I>) -> Result<usize, EmulationError> {
+        let mut instructions = 0;
+        while !self.take_frame_done() {
+            self.step(cpu)?;
+            instructions += 1;
+        }
+        Ok(instructions)
+    }
+
+    /// Whether a frame ended since the last call.
+    pub fn take_frame_done(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_done, false)
+    }
+
+    pub fn frame_cycle(&self) -> u32 {
+        self.frame_cycle
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+/// Position within the frame and the counters. The frame length and interrupt
+/// points belong to the machine and must match.
+impl Snapshot for Scheduler {
+    fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cycles_per_frame.to_le_bytes());
+        out.extend_from_slice(&self.frame_cycle.to_le_bytes());
+        out.extend_from_slice(&(self.next_interrupt as u32).to_le_bytes());
+        out.push(u8::from(self.frame_done));
+        out.extend_from_slice(&self.total_cycles.to_le_bytes());
+        out.extend_from_slice(&self.frames.to_le_bytes());
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        let cycles_per_frame = input.u32()?;
+        let frame_cycle = input.u32()?;
+        let next_interrupt = input.u32()? as usize;
+        if cycles_per_frame != self.cycles_per_frame
+            || frame_cycle >= cycles_per_frame
+            || next_interrupt > self.interrupts.len()
+        {
+            return Err(StateError::Corrupt);
+        }
+        self.frame_cycle = frame_cycle;
+        self.next_interrupt = next_interrupt;
+        self.frame_done = input.bool()?;
+        self.total_cycles = input.u64()?;
+        self.frames = input.u64()?;
+        Ok(())
+    }
+}
+
+/// Keeps emulated time in step with a monotonic clock.
+pub struct Pacer {
+    start: Instant,
+    frames: u64,
+    clock_hz: u64,
+    cycles_per_frame: u64,
+}
+
+impl Pacer {
+    /// Paces frames of `cycles_per_frame` T-states on a CPU running at `clock_hz`.
+    pub fn new(clock_hz: u32, cycles_per_frame: u32) -> Self {
+        Pacer {
+            start: Instant::now(),
+            frames: 0,
+            clock_hz: u64::from(clock_hz),
+            cycles_per_frame: u64::from(cycles_per_frame),
+        }
+    }
+
+    /// Sleeps until the real time of the next frame boundary. When the host falls more
+    /// than a quarter second behind, the schedule is restarted instead of fast-forwarding.
+    pub fn wait(&mut self) {
+        self.frames += 1;
+        let cycles = u128::from(self.frames * self.cycles_per_frame);
+        let target = Duration::from_nanos((cycles * 1_000_000_000 / u128::from(self.clock_hz)) as u64);
+        let elapsed = self.start.elapsed();
+        if target > elapsed {
+            thread::sleep(target - elapsed);
+        } else if elapsed - target > Duration::from_millis(250) {
+            self.start = Instant::now();
+            self.frames = 0;
+        }
+    }
+}
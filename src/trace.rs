@@ -0,0 +1,108 @@
+use crate::cpu::debugging::Instructioninfo;
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+/// First bytes of a binary trace, followed by `Instructioninfo::ENCODED_LEN` byte records.
+pub const MAGIC: &[u8; 8] = b"8080TRC\x01";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceFormat {
+    /// One line per instruction, meant for reading and diffing.
+    Text,
+    /// Fixed size records, meant for long runs. `TraceReader` reads them back.
+    Binary,
+}
+
+/// Which instructions end up in a trace. Empty criteria match everything.
+#[derive(Clone, Debug, Default)]
+pub struct TraceFilter {
+    pub pc: Option<RangeInclusive<u16>>,
+    /// instruction numbers, counting from 0
+    pub window: Option<RangeInclusive<u64>>,
+    pub opcodes: Vec<u8>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, instr_n: u64, pc: u16, opcode: u8) -> bool {
+        self.window.as_ref().is_none_or(|window| window.contains(&instr_n))
+            && self.pc.as_ref().is_none_or(|range| range.contains(&pc))
+            && (self.opcodes.is_empty() || self.opcodes.contains(&opcode))
+    }
+}
+
+/// Writes an `Instructioninfo` for every executed instruction that passes the filter.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    format: TraceFormat,
+    filter: TraceFilter,
+}
+
+impl Tracer {
+    pub fn new(mut out: Box<dyn Write>, format: TraceFormat, filter: TraceFilter) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            out.write_all(MAGIC)?;
+        }
+        Ok(Tracer {
+            out,
+            format,
+            filter,
+        })
+    }
+
+    pub fn create(path: &Path, format: TraceFormat, filter: TraceFilter) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Self::new(Box::new(BufWriter::new(file)), format, filter)
+    }
+
+    /// Writes `info` if it passes the filter.
+    pub fn record(&mut self, info: &Instructioninfo) -> io::Result<()> {
+        if !self.filter.matches(info.instr_n, info.pc, info.opcode) {
+            return Ok(());
+        }
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", info),
+            TraceFormat::Binary => self.out.write_all(&info.encode()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Reads the records of a binary trace.
+pub struct TraceReader<R: Read> {
+    input: R,
+}
+
+impl<R: Read> TraceReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a binary 8080 trace"));
+        }
+        Ok(TraceReader { input })
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<Instructioninfo>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = [0; Instructioninfo::ENCODED_LEN];
+        let mut filled = 0;
+        while filled < record.len() {
+            match self.input.read(&mut record[filled..]) {
+                Ok(0) if filled == 0 => return None,
+                Ok(0) => return Some(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "truncated trace record"))),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(Instructioninfo::decode(&record)))
+    }
+}
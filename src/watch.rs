@@ -0,0 +1,198 @@
+use crate::memory::Bus;
+use crate::savestate::{Input, Snapshot, StateError};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One memory access of the CPU. For reads `old` and `new` are both the value read,
+/// for writes `new` is the value the CPU wrote, even if the memory ignored it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// Bus adapter that remembers every read and write made through it while enabled.
+pub struct Recorder<M: Bus> {
+    inner: M,
+    enabled: bool,
+    accesses: Vec<MemoryAccess>,
+}
+
+impl<M: Bus> Recorder<M> {
+    pub fn new(inner: M) -> Self {
+        Recorder {
+            inner,
+            enabled: false,
+            accesses: Vec::new(),
+        }
+    }
+
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut M {
+        &mut self.inner
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        self.accesses.clear();
+    }
+
+    /// The accesses since the last call.
+    pub fn take_accesses(&mut self) -> Vec<MemoryAccess> {
+        std::mem::take(&mut self.accesses)
+    }
+}
+
+impl<M: Bus> Bus for Recorder<M> {
+    fn peek(&self, addr: u16) -> u8 {
+        self.inner.peek(addr)
+    }
+
+    fn read(&mut self, addr: u16) -> u8 {
+        let value = self.inner.read(addr);
+        if self.enabled {
+            self.accesses.push(MemoryAccess {
+                kind: AccessKind::Read,
+                addr,
+                old: value,
+                new: value,
+            });
+        }
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        if self.enabled {
+            self.accesses.push(MemoryAccess {
+                kind: AccessKind::Write,
+                addr,
+                old: self.inner.peek(addr),
+                new: value,
+            });
+        }
+        self.inner.write(addr, value);
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        self.inner.tick(cycles);
+    }
+
+    fn take_fault(&mut self) -> Option<u16> {
+        self.inner.take_fault()
+    }
+}
+
+impl<M: Bus + Snapshot> Snapshot for Recorder<M> {
+    fn save(&self, out: &mut Vec<u8>) {
+        self.inner.save(out);
+    }
+
+    fn restore(&mut self, input: &mut Input) -> Result<(), StateError> {
+        self.inner.restore(input)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Fires on accesses of the watched kind to an address range, optionally only when
+/// a given value is read or written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub watch: Watch,
+    pub value: Option<u8>,
+    /// only log hits instead of pausing
+    pub log_only: bool,
+}
+
+impl Watchpoint {
+    /// Parses `ADDR[-END]` followed by any of `r`, `w`, `rw`, `=VALUE` and `log`,
+    /// separated by commas or spaces. Watches writes by default.
+    pub fn parse<F>(spec: &str, parse_number: F) -> Result<Self, String>
+    where
+        F: Fn(&str) -> Result<u64, String>,
+    {
+        let mut words = spec.split([',', ' ']).filter(|word| !word.is_empty());
+        let range = words.next().ok_or("missing watchpoint address")?;
+        let addr = |text: &str| {
+            let addr = parse_number(text)?;
+            u16::try_from(addr).map_err(|_| format!("address {:#x} is above $ffff", addr))
+        };
+        let range = match range.split_once('-') {
+            Some((start, end)) => addr(start)?..=addr(end)?,
+            None => addr(range)?..=addr(range)?,
+        };
+        if range.is_empty() {
+            return Err(format!("watch range {:04x}-{:04x} is empty", range.start(), range.end()));
+        }
+        let mut watchpoint = Watchpoint {
+            range,
+            watch: Watch::Write,
+            value: None,
+            log_only: false,
+        };
+        for word in words {
+            match word {
+                "r" => watchpoint.watch = Watch::Read,
+                "w" => watchpoint.watch = Watch::Write,
+                "rw" => watchpoint.watch = Watch::ReadWrite,
+                "log" => watchpoint.log_only = true,
+                _ => match word.strip_prefix('=') {
+                    Some(value) => {
+                        let value = parse_number(value)?;
+                        watchpoint.value = Some(u8::try_from(value).map_err(|_| format!("{:#x} is not a byte", value))?);
+                    }
+                    None => return Err(format!("unknown watchpoint option '{}'", word)),
+                },
+            }
+        }
+        Ok(watchpoint)
+    }
+
+    pub fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind = match (self.watch, access.kind) {
+            (Watch::ReadWrite, _) => true,
+            (Watch::Read, kind) => kind == AccessKind::Read,
+            (Watch::Write, kind) => kind == AccessKind::Write,
+        };
+        kind && self.range.contains(&access.addr) && self.value.is_none_or(|value| value == access.new)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04x}", self.range.start())?;
+        if self.range.end() != self.range.start() {
+            write!(f, "-{:04x}", self.range.end())?;
+        }
+        let watch = match self.watch {
+            Watch::Read => "r",
+            Watch::Write => "w",
+            Watch::ReadWrite => "rw",
+        };
+        write!(f, " {}", watch)?;
+        if let Some(value) = self.value {
+            write!(f, " ={:02x}", value)?;
+        }
+        if self.log_only {
+            write!(f, " log")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use rust_8080::cpm::{self, Exit};
+use std::fs;
+use std::path::PathBuf;
+
+/// The diagnostics are not part of the repository. Put CPUDIAG.COM, TST8080.COM,
+/// 8080PRE.COM and 8080EXM.COM into tests/roms and run `cargo test -- --ignored`.
+fn diagnostic(name: &str) -> Vec<u8> {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms").join(name);
+    fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// Lines reporting an instruction group, with whether the group passed.
+fn groups(output: &str) -> Vec<(&str, bool)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            if line.contains("PASS!") {
+                Some((line.trim(), true))
+            } else if line.contains("ERROR") {
+                Some((line.trim(), false))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn run_diagnostic(name: &str, max_instructions: u64, passed: &str) {
+    let program = diagnostic(name);
+    let run = cpm::run(&program, max_instructions);
+    println!("{}", run.output);
+    let groups = groups(&run.output);
+    for (group, ok) in &groups {
+        println!("{} {}", if *ok { "pass" } else { "FAIL" }, group);
+    }
+    let failed: Vec<&str> = groups.iter().filter(|(_, ok)| !ok).map(|(group, _)| *group).collect();
+    assert!(failed.is_empty(), "{} failed groups:\n{}", name, failed.join("\n"));
+    assert!(matches!(run.exit, Exit::WarmBoot), "{} did not finish ({:?}):\n{}", name, run.exit, run.output);
+    assert!(run.output.contains(passed), "{} did not report success:\n{}", name, run.output);
+}
+
+#[test]
+fn bdos_console_and_warm_boot() {
+    let program = [
+        0x2a, 0x06, 0x00, // LHLD $0006
+        0xf9, // SPHL
+        0x0e, 0x09, // MVI C,$09
+        0x11, 0x16, 0x01, // LXI D,$0116
+        0xcd, 0x05, 0x00, // CALL $0005
+        0x0e, 0x02, // MVI C,$02
+        0x1e, b'!', // MVI E,'!'
+        0xcd, 0x05, 0x00, // CALL $0005
+        0xc3, 0x00, 0x00, // JMP $0000
+        b'H', b'E', b'L', b'L', b'O', b'$',
+    ];
+    let run = cpm::run(&program, 1000);
+    assert!(matches!(run.exit, Exit::WarmBoot), "{:?}", run.exit);
+    assert_eq!(run.output, "HELLO!");
+    assert_eq!(run.instructions, 9);
+}
+
+#[test]
+fn instruction_limit() {
+    // JMP $0100
+    let run = cpm::run(&[0xc3, 0x00, 0x01], 500);
+    assert!(matches!(run.exit, Exit::Limit), "{:?}", run.exit);
+    assert_eq!(run.instructions, 500);
+}
+
+#[test]
+#[ignore = "needs tests/roms/CPUDIAG.COM"]
+fn cpudiag() {
+    run_diagnostic("CPUDIAG.COM", 100_000_000, "CPU IS OPERATIONAL");
+}
+
+#[test]
+#[ignore = "needs tests/roms/TST8080.COM"]
+fn tst8080() {
+    run_diagnostic("TST8080.COM", 100_000_000, "CPU IS OPERATIONAL");
+}
+
+#[test]
+#[ignore = "needs tests/roms/8080PRE.COM"]
+fn preliminary_8080pre() {
+    run_diagnostic("8080PRE.COM", 100_000_000, "Preliminary tests complete");
+}
+
+/// Takes billions of instructions, run with `cargo test --release -- --ignored`.
+#[test]
+#[ignore = "needs tests/roms/8080EXM.COM, takes billions of instructions"]
+fn exerciser_8080exm() {
+    run_diagnostic("8080EXM.COM", u64::MAX, "Tests complete");
+}
@@ -0,0 +1,111 @@
+use rust_8080::debugger::{Debugger, Resume};
+use rust_8080::report::{DumpFormat, Reporter};
+use rust_8080::{Bus, Cpu8080};
+
+const PROGRAM: [u8; 5] = [
+    0xcd, 0x10, 0x00, // CALL $0010
+    0xff, // RST 7
+    0x00, // NOP
+];
+
+fn machine() -> (Cpu8080, Debugger) {
+    let mut cpu = Cpu8080::new();
+    cpu.memory_mut().load(0x0000, &PROGRAM);
+    // NOP ; RET
+    cpu.memory_mut().load(0x0010, &[0x00, 0xc9]);
+    // RET
+    cpu.memory_mut().load(0x0038, &[0xc9]);
+    cpu.state_mut().sp = 0x2400;
+    (cpu, Debugger::new(true))
+}
+
+/// Runs `line` and returns its output.
+fn execute(cpu: &mut Cpu8080, debugger: &mut Debugger, line: &str) -> Result<(Option<Resume>, String), String> {
+    let reporter = Reporter::new(DumpFormat::Hexdump, 0);
+    let mut out = Vec::new();
+    let resume = debugger.execute(cpu, &reporter, line, &mut out)?;
+    Ok((resume, String::from_utf8(out).unwrap()))
+}
+
+/// Resumes the way the emulation loop does, the current instruction runs before
+/// the debugger is asked again. Returns the number of instructions executed.
+fn resume(cpu: &mut Cpu8080, debugger: &mut Debugger) -> u32 {
+    for executed in 1..1000 {
+        cpu.step().unwrap();
+        if debugger.should_break(cpu.pc()) {
+            return executed;
+        }
+    }
+    panic!("the debugger did not stop, PC {:04x}", cpu.pc());
+}
+
+#[test]
+fn step_counts_instructions() {
+    let (mut cpu, mut debugger) = machine();
+    assert!(debugger.should_break(0x0000));
+    assert_eq!(execute(&mut cpu, &mut debugger, "s 3"), Ok((Some(Resume::Run), String::new())));
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (3, 0x0003));
+    assert_eq!(execute(&mut cpu, &mut debugger, "step").unwrap().0, Some(Resume::Run));
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (1, 0x0038));
+
+    assert_eq!(execute(&mut cpu, &mut debugger, "s 0"), Err("step count must be at least 1".to_string()));
+    assert_eq!(execute(&mut cpu, &mut debugger, "s x"), Err("'x' is not a count".to_string()));
+    assert_eq!(execute(&mut cpu, &mut debugger, "q"), Ok((Some(Resume::Quit), String::new())));
+    assert_eq!(execute(&mut cpu, &mut debugger, ""), Ok((None, String::new())));
+    assert_eq!(execute(&mut cpu, &mut debugger, "go"), Err("unknown command 'go', try help".to_string()));
+}
+
+#[test]
+fn next_steps_over_call_and_rst() {
+    let (mut cpu, mut debugger) = machine();
+    // CALL, NOP, RET and back after the three bytes of the CALL
+    execute(&mut cpu, &mut debugger, "n").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (3, 0x0003));
+    // RST and RET
+    execute(&mut cpu, &mut debugger, "next").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (2, 0x0004));
+    // anything else is a single step
+    execute(&mut cpu, &mut debugger, "n").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (1, 0x0005));
+}
+
+#[test]
+fn until_and_breakpoints() {
+    let (mut cpu, mut debugger) = machine();
+    execute(&mut cpu, &mut debugger, "u 11").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (2, 0x0011));
+    // the temporary breakpoint is gone once reached
+    assert_eq!(debugger.breakpoints().count(), 0);
+
+    assert_eq!(execute(&mut cpu, &mut debugger, "b $38"), Ok((None, "breakpoint at 0038\n".to_string())));
+    assert_eq!(execute(&mut cpu, &mut debugger, "break 0x4"), Ok((None, "breakpoint at 0004\n".to_string())));
+    assert_eq!(
+        execute(&mut cpu, &mut debugger, "b"),
+        Ok((None, "breakpoint at 0004\nbreakpoint at 0038\n".to_string()))
+    );
+    execute(&mut cpu, &mut debugger, "c").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (2, 0x0038));
+    execute(&mut cpu, &mut debugger, "continue").unwrap();
+    assert_eq!((resume(&mut cpu, &mut debugger), cpu.pc()), (1, 0x0004));
+
+    assert_eq!(execute(&mut cpu, &mut debugger, "d 38"), Ok((None, String::new())));
+    assert_eq!(execute(&mut cpu, &mut debugger, "delete 38"), Err("no breakpoint at 0038".to_string()));
+    assert_eq!(debugger.breakpoints().copied().collect::<Vec<_>>(), [0x0004]);
+    assert_eq!(execute(&mut cpu, &mut debugger, "b 1g"), Err("'1g' is not a hex number".to_string()));
+    assert_eq!(execute(&mut cpu, &mut debugger, "u"), Err("missing address".to_string()));
+}
+
+#[test]
+fn poke_and_peek() {
+    let (mut cpu, mut debugger) = machine();
+    assert_eq!(execute(&mut cpu, &mut debugger, "poke 2000 de ad 42"), Ok((None, String::new())));
+    assert_eq!((0x2000..0x2003).map(|addr| cpu.memory().peek(addr)).collect::<Vec<_>>(), [0xde, 0xad, 0x42]);
+    let (_, dump) = execute(&mut cpu, &mut debugger, "x 2000 3").unwrap();
+    assert_eq!(dump, format!("2000  {:<47}  ..B\n", "de ad 42"));
+    let (_, dump) = execute(&mut cpu, &mut debugger, "peek 2000").unwrap();
+    assert_eq!(dump.lines().count(), 4);
+
+    assert_eq!(execute(&mut cpu, &mut debugger, "poke 2000"), Err("usage: poke ADDR BYTE...".to_string()));
+    assert_eq!(execute(&mut cpu, &mut debugger, "poke 2000 100"), Err("100 is not a byte".to_string()));
+    assert_eq!(execute(&mut cpu, &mut debugger, "poke"), Err("missing address".to_string()));
+}
@@ -0,0 +1,68 @@
+use rust_8080::disasm::{self, decode, disassemble_image};
+
+#[test]
+fn one_of_each_group() {
+    let cases: [(&[u8], &str); 22] = [
+        (&[0x00], "NOP"),
+        (&[0x78], "MOV A,B"),
+        (&[0x77], "MOV M,A"),
+        (&[0x76], "HLT"),
+        (&[0x36, 0x5a], "MVI M,$5A"),
+        (&[0x31, 0x00, 0x24], "LXI SP,$2400"),
+        (&[0x3a, 0xcd, 0xab], "LDA $ABCD"),
+        (&[0x09], "DAD B"),
+        (&[0x86], "ADD M"),
+        (&[0x9f], "SBB A"),
+        (&[0xbb], "CMP E"),
+        (&[0xfe, 0x10], "CPI $10"),
+        (&[0xc3, 0x00, 0x01], "JMP $0100"),
+        (&[0xca, 0x34, 0x12], "JZ $1234"),
+        (&[0xcd, 0x05, 0x00], "CALL $0005"),
+        (&[0xfc, 0x34, 0x12], "CM $1234"),
+        (&[0xc9], "RET"),
+        (&[0xd0], "RNC"),
+        (&[0xff], "RST 7"),
+        (&[0xdb, 0x01], "IN $01"),
+        (&[0xd3, 0x06], "OUT $06"),
+        (&[0xf5], "PUSH PSW"),
+    ];
+    for (bytes, text) in cases {
+        assert_eq!(decode(bytes).as_deref(), Some(text), "{:02x?}", bytes);
+        assert_eq!(disasm::size(bytes[0]), bytes.len(), "{:02x?}", bytes);
+    }
+}
+
+#[test]
+fn undocumented_aliases_are_marked() {
+    let cases: [(&[u8], &str); 6] = [
+        (&[0x08], "*NOP"),
+        (&[0x38], "*NOP"),
+        (&[0xd9], "*RET"),
+        (&[0xcb, 0x00, 0x10], "*JMP $1000"),
+        (&[0xdd, 0x00, 0x10], "*CALL $1000"),
+        (&[0xfd, 0x00, 0x10], "*CALL $1000"),
+    ];
+    for (bytes, text) in cases {
+        assert_eq!(decode(bytes).as_deref(), Some(text), "{:02x?}", bytes);
+    }
+    // every opcode decodes, and only the aliases carry the mark
+    for opcode in 0..=0xffu8 {
+        let text = decode(&[opcode, 0, 0]).unwrap();
+        let alias = matches!(opcode, 0x08 | 0x10 | 0x18 | 0x20 | 0x28 | 0x30 | 0x38 | 0xcb | 0xd9 | 0xdd | 0xed | 0xfd);
+        assert_eq!(text.starts_with('*'), alias, "{:02x} {}", opcode, text);
+    }
+}
+
+#[test]
+fn cut_off_operands() {
+    assert_eq!(decode(&[]), None);
+    assert_eq!(decode(&[0xc3, 0x00]), None);
+
+    // MVI A,$01 ; JMP with one operand byte left
+    let listing = disassemble_image(&[0x3e, 0x01, 0xc3, 0x00], 0x0100);
+    assert_eq!(listing.len(), 2);
+    assert_eq!((listing[0].addr, listing[0].text.as_str()), (0x0100, "MVI A,$01"));
+    assert_eq!((listing[1].addr, listing[1].bytes.as_slice()), (0x0102, &[0xc3, 0x00][..]));
+    assert_eq!(listing[1].text, "DB $C3,$00");
+    assert_eq!(listing[0].to_string(), "0100  3e 01     MVI A,$01");
+}
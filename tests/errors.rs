@@ -0,0 +1,57 @@
+use rust_8080::{Cpu8080, EmulationError, MemoryMap};
+
+fn load(program: &[u8]) -> Cpu8080 {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    cpu.memory_mut().load(0, program);
+    cpu
+}
+
+#[test]
+fn undocumented_opcodes_are_illegal_when_strict() {
+    // NOP ; *NOP
+    let mut cpu = load(&[0x00, 0x08]);
+    cpu.set_undocumented(false);
+    assert_eq!(cpu.step().unwrap(), 4);
+    match cpu.step() {
+        Err(EmulationError::IllegalOpcode { pc: 0x0001, opcode: 0x08 }) => {}
+        other => panic!("expected an illegal opcode, got {:?}", other),
+    }
+    assert_eq!(cpu.pc(), 0x0001);
+}
+
+#[test]
+fn stack_leaving_its_area() {
+    // LXI SP,$1000 ; PUSH B ; POP B ; POP B
+    let program = [0x31, 0x00, 0x10, 0xc5, 0xc1, 0xc1];
+    let mut cpu = load(&program);
+    cpu.set_stack_limits(Some(0x0ffe..=0x1000));
+    for _ in 0..3 {
+        cpu.step().unwrap();
+    }
+    match cpu.step() {
+        Err(EmulationError::StackUnderflow { pc: 0x0005, opcode: 0xc1, sp: 0x1002, .. }) => {}
+        other => panic!("expected a stack underflow, got {:?}", other),
+    }
+
+    // LXI SP,$1000 ; PUSH B ; PUSH B
+    let mut overflow = load(&[0x31, 0x00, 0x10, 0xc5, 0xc5]);
+    overflow.set_stack_limits(Some(0x0ffe..=0x1000));
+    for _ in 0..2 {
+        overflow.step().unwrap();
+    }
+    assert!(matches!(overflow.step(), Err(EmulationError::StackOverflow { sp: 0x0ffc, .. })));
+}
+
+#[test]
+fn halt_with_interrupts_disabled() {
+    // DI ; HLT
+    let mut cpu = load(&[0xf3, 0x76]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.step().unwrap(), 7);
+    match cpu.step() {
+        Err(error @ EmulationError::HaltDeadlock { pc: 0x0001 }) => {
+            assert_eq!(error.to_string(), "halted with interrupts disabled at 0001");
+        }
+        other => panic!("expected a deadlock, got {:?}", other),
+    }
+}
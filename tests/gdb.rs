@@ -0,0 +1,125 @@
+use rust_8080::debugger::{Debugger, Resume};
+use rust_8080::gdb::GdbStub;
+use rust_8080::watch::Recorder;
+use rust_8080::{Cpu8080, MemoryMap};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+/// A free port on the loopback interface.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+/// Runs `program` under the stub the way the emulator does and returns the final PC.
+fn serve(port: u16, program: &'static [u8]) -> thread::JoinHandle<u16> {
+    thread::spawn(move || {
+        let mut stub = GdbStub::accept(("127.0.0.1", port)).unwrap();
+        let mut cpu = Cpu8080::with_memory(Recorder::new(MemoryMap::new()));
+        cpu.memory_mut().inner_mut().load(0, program);
+        let mut debugger = Debugger::new(true);
+        loop {
+            if debugger.should_break(cpu.pc()) && stub.serve(&mut cpu, &mut debugger).unwrap() == Resume::Quit {
+                return cpu.pc();
+            }
+            cpu.memory_mut().set_enabled(!debugger.watchpoints().is_empty());
+            let (pc, bytes) = (cpu.pc(), cpu.next_instruction());
+            cpu.step().unwrap();
+            let accesses = cpu.memory_mut().take_accesses();
+            debugger.check_accesses(pc, bytes, &accesses, &mut io::sink()).unwrap();
+        }
+    })
+}
+
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn connect(port: u16) -> Self {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+                stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+                return Client { stream };
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("the stub did not listen on port {}", port);
+    }
+
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    /// Sends a packet and waits for the acknowledgement.
+    fn send(&mut self, data: &str) {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(self.byte(), b'+', "packet {} not acknowledged", data);
+    }
+
+    fn reply(&mut self) -> String {
+        while self.byte() != b'$' {}
+        let mut data = Vec::new();
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => data.push(byte),
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        let sum = data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        assert_eq!(std::str::from_utf8(&checksum).unwrap(), format!("{:02x}", sum));
+        String::from_utf8(data).unwrap()
+    }
+
+    fn ask(&mut self, data: &str) -> String {
+        self.send(data);
+        self.reply()
+    }
+}
+
+/// PC as the last register of a `g` reply.
+fn pc(registers: &str) -> &str {
+    &registers[20..]
+}
+
+#[test]
+fn scripted_session() {
+    static PROGRAM: [u8; 10] = [
+        0x21, 0x00, 0x20, // LXI H,$2000
+        0x00, // NOP
+        0x36, 0x55, // MVI M,$55
+        0x00, // NOP
+        0xc3, 0x06, 0x00, // JMP $0006
+    ];
+    let port = free_port();
+    let server = serve(port, &PROGRAM);
+    let mut client = Client::connect(port);
+
+    assert_eq!(client.ask("?"), "S05");
+    // AF BC DE HL SP PC, little endian
+    assert_eq!(client.ask("g"), "020000000000000000f00000");
+    assert_eq!(client.ask("m0000,3"), "210020");
+    assert_eq!(client.ask("M1000,2:abcd"), "OK");
+    assert_eq!(client.ask("m1000,2"), "abcd");
+    assert_eq!(client.ask("m1000,zz"), "E01");
+
+    assert_eq!(client.ask("Z0,3,1"), "OK");
+    assert_eq!(client.ask("c"), "S05");
+    assert_eq!(pc(&client.ask("g")), "0300");
+
+    assert_eq!(client.ask("Z2,2000,1"), "OK");
+    assert_eq!(client.ask("c"), "T05watch:2000;");
+    assert_eq!(pc(&client.ask("g")), "0600");
+    assert_eq!(client.ask("m2000,1"), "55");
+
+    assert_eq!(client.ask("s"), "S05");
+    assert_eq!(pc(&client.ask("g")), "0700");
+
+    client.send("k");
+    assert_eq!(server.join().unwrap(), 0x0007);
+}
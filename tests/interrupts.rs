@@ -0,0 +1,80 @@
+use rust_8080::{Bus, Cpu8080, MemoryMap};
+
+/// `program` at $0000 with the stack below $2400.
+fn load(program: &[u8]) -> Cpu8080 {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    cpu.memory_mut().load(0, program);
+    cpu.set_sp(0x2400);
+    cpu
+}
+
+/// The return address on top of the stack.
+fn pushed(cpu: &Cpu8080) -> u16 {
+    u16::from_le_bytes([cpu.memory().peek(cpu.sp()), cpu.memory().peek(cpu.sp().wrapping_add(1))])
+}
+
+#[test]
+fn ei_takes_effect_after_the_next_instruction() {
+    // EI ; NOP ; NOP
+    let mut cpu = load(&[0xfb, 0x00, 0x00]);
+    assert_eq!(cpu.step().unwrap(), 4);
+    cpu.interrupt(1);
+    assert!(cpu.int_enabled());
+    assert!(!cpu.acknowledges_interrupt());
+    assert_eq!(cpu.step().unwrap(), 4);
+    assert_eq!((cpu.pc(), cpu.interrupt_pending()), (0x0002, true));
+
+    assert!(cpu.acknowledges_interrupt());
+    assert_eq!(cpu.step().unwrap(), 11);
+    assert_eq!((cpu.pc(), cpu.sp(), pushed(&cpu)), (0x0008, 0x23fe, 0x0002));
+    assert!(!cpu.interrupt_pending());
+    assert!(!cpu.int_enabled());
+}
+
+#[test]
+fn rst_wakes_hlt() {
+    // EI ; HLT
+    let mut cpu = load(&[0xfb, 0x76]);
+    cpu.step().unwrap();
+    assert_eq!(cpu.step().unwrap(), 7);
+    assert!(cpu.halted());
+    for _ in 0..3 {
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.pc(), 0x0002);
+        assert!(cpu.halted());
+    }
+
+    cpu.interrupt(2);
+    assert_eq!(cpu.step().unwrap(), 11);
+    assert!(!cpu.halted());
+    // returns to the instruction after the HLT
+    assert_eq!((cpu.pc(), pushed(&cpu)), (0x0010, 0x0002));
+}
+
+#[test]
+fn request_waits_while_disabled() {
+    // DI ; NOP ; EI ; NOP ; NOP
+    let mut cpu = load(&[0xf3, 0x00, 0xfb, 0x00, 0x00]);
+    cpu.step().unwrap();
+    cpu.interrupt(7);
+    for pc in [0x0002, 0x0003, 0x0004] {
+        cpu.step().unwrap();
+        assert_eq!((cpu.pc(), cpu.interrupt_pending()), (pc, true));
+    }
+    assert_eq!(cpu.step().unwrap(), 11);
+    assert_eq!((cpu.pc(), pushed(&cpu)), (0x0038, 0x0004));
+}
+
+#[test]
+fn injected_call() {
+    // EI ; NOP ; NOP
+    let mut cpu = load(&[0xfb, 0x00, 0x00]);
+    cpu.step().unwrap();
+    cpu.step().unwrap();
+    // CALL $3000
+    cpu.request_interrupt([0xcd, 0x00, 0x30]);
+    assert_eq!(cpu.next_instruction(), [0xcd, 0x00, 0x30]);
+    assert_eq!(cpu.step().unwrap(), 17);
+    assert_eq!((cpu.pc(), cpu.sp(), pushed(&cpu)), (0x3000, 0x23fe, 0x0002));
+    assert!(!cpu.int_enabled());
+}
@@ -0,0 +1,72 @@
+use rust_8080::invaders::{Button, DipSwitches, InvadersIo};
+
+/// Port and bit of every button on the Space Invaders schematics.
+const WIRING: [(Button, u8, u8); 10] = [
+    (Button::Coin, 1, 0),
+    (Button::Start2, 1, 1),
+    (Button::Start1, 1, 2),
+    (Button::Fire1, 1, 4),
+    (Button::Left1, 1, 5),
+    (Button::Right1, 1, 6),
+    (Button::Tilt, 2, 2),
+    (Button::Fire2, 2, 4),
+    (Button::Left2, 2, 5),
+    (Button::Right2, 2, 6),
+];
+
+fn ports(io: &InvadersIo) -> [u8; 3] {
+    [io.port(0), io.port(1), io.port(2)]
+}
+
+#[test]
+fn buttons_set_their_port_bit() {
+    let mut io = InvadersIo::new();
+    io.dip_switches = DipSwitches {
+        lives: 3,
+        bonus_life_at_1000: false,
+        coin_info: true,
+    };
+    let idle = ports(&io);
+    // port 0 bits 1-3 and port 1 bit 3 are tied high, no DIP switch bit is set
+    assert_eq!(idle, [0b0000_1110, 0b0000_1000, 0b0000_0000]);
+    assert_eq!(WIRING.len(), Button::ALL.len());
+    for (button, port, bit) in WIRING {
+        io.set_button(button, true);
+        let mut expected = idle;
+        expected[usize::from(port)] |= 1 << bit;
+        // port 0 mirrors the player 1 controls
+        if port == 1 && bit >= 4 {
+            expected[0] |= 1 << bit;
+        }
+        assert_eq!(ports(&io), expected, "{:?}", button);
+        io.set_button(button, false);
+        assert_eq!(ports(&io), idle, "{:?}", button);
+    }
+
+    io.set_button(Button::Fire1, true);
+    io.set_button(Button::Fire2, true);
+    assert_eq!(io.buttons(), [0b0001_0000, 0b0001_0000]);
+    let mut copy = InvadersIo::new();
+    copy.set_buttons(io.buttons());
+    assert_eq!(copy.port(1), io.port(1));
+}
+
+#[test]
+fn dip_switches_in_port_2() {
+    let mut io = InvadersIo::new();
+    for (lives, bits) in [(3, 0b00), (4, 0b01), (5, 0b10), (6, 0b11)] {
+        io.dip_switches.lives = lives;
+        assert_eq!(io.port(2) & 0b11, bits, "{} lives", lives);
+    }
+    io.dip_switches = DipSwitches {
+        lives: 3,
+        bonus_life_at_1000: true,
+        coin_info: true,
+    };
+    assert_eq!(io.port(2), 0b0000_1000);
+    io.dip_switches.bonus_life_at_1000 = false;
+    io.dip_switches.coin_info = false;
+    assert_eq!(io.port(2), 0b1000_0000);
+    // the switches do not show in ports 0 and 1
+    assert_eq!((io.port(0), io.port(1)), (0b0000_1110, 0b0000_1000));
+}
@@ -0,0 +1,41 @@
+use rust_8080::lockstep::{Expected, Lockstep, LockstepError};
+use rust_8080::{Cpu8080, MemoryMap};
+
+#[test]
+fn parses_log_and_trace_lines() {
+    let log = Expected::parse("PC: 0100, AF: 1257, BC: 0000, DE: 0000, HL: abcd, SP: f000, CYC: 7").unwrap().unwrap();
+    assert_eq!(log.pc, Some(0x0100));
+    assert_eq!((log.a, log.f), (Some(0x12), Some(0x57)));
+    assert_eq!((log.h, log.l), (Some(0xab), Some(0xcd)));
+    assert_eq!(log.cycles, Some(7));
+
+    let trace = "         2           21  0104  0e 09     MVI C,$09      A=00 BC=0000 DE=0000 HL=fe00 SP=fe00 F=-Z-P-";
+    let trace = Expected::parse(trace).unwrap().unwrap();
+    assert_eq!((trace.pc, trace.cycles), (Some(0x0104), Some(21)));
+    assert_eq!((trace.sp, trace.f), (Some(0xfe00), Some(0x46)));
+
+    assert_eq!(Expected::parse("  # comment").unwrap(), None);
+    assert!(Expected::parse("nothing here").is_err());
+    assert!(Expected::parse("PC: xyz").is_err());
+}
+
+#[test]
+fn stops_at_first_mismatch() {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    // MVI A,$01 ; INR A ; INR A
+    cpu.memory_mut().load(0, &[0x3e, 0x01, 0x3c, 0x3c]);
+    let reference = "PC=0000 A=00\nPC=0002 A=01\n\nPC=0003 A=03\n";
+    let mut lockstep = Lockstep::new(reference.as_bytes());
+    assert!(lockstep.check(&cpu, 0).unwrap());
+    cpu.step().unwrap();
+    assert!(lockstep.check(&cpu, 7).unwrap());
+    cpu.step().unwrap();
+    match lockstep.check(&cpu, 12) {
+        Err(LockstepError::Diverged(divergence)) => {
+            assert_eq!((divergence.instruction, divergence.line), (2, 4));
+            let fields: Vec<&str> = divergence.mismatches.iter().map(|mismatch| mismatch.field.as_str()).collect();
+            assert_eq!(fields, ["a"]);
+        }
+        other => panic!("expected a divergence, got {:?}", other),
+    }
+}
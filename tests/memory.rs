@@ -0,0 +1,101 @@
+use rust_8080::memory::UnmappedPolicy;
+use rust_8080::timing::Scheduler;
+use rust_8080::{Bus, Cpu8080, EmulationError, MemoryMap};
+
+/// 8 KiB of RAM from $0000.
+fn small_map(policy: UnmappedPolicy) -> MemoryMap {
+    let mut map = MemoryMap::unmapped();
+    map.map_ram(0x0000, 0x2000);
+    map.set_unmapped_policy(policy);
+    map
+}
+
+#[test]
+fn open_bus_and_mirror() {
+    let mut open = small_map(UnmappedPolicy::OpenBus);
+    open.write(0x0010, 0x42);
+    open.write(0x2010, 0x99);
+    assert_eq!((open.read(0x2010), open.read(0x0010)), (0xff, 0x42));
+
+    let mut mirror = small_map(UnmappedPolicy::Mirror);
+    mirror.write(0xe010, 0x99);
+    assert_eq!((mirror.read(0x0010), mirror.read(0x2010)), (0x99, 0x99));
+    assert_eq!(mirror.take_fault(), None);
+}
+
+#[test]
+fn trap_reports_the_first_unmapped_access() {
+    let mut cpu = Cpu8080::with_memory(small_map(UnmappedPolicy::Trap));
+    // LXI SP,$0000 ; PUSH B
+    cpu.memory_mut().load(0, &[0x31, 0x00, 0x00, 0xc5]);
+    cpu.step().unwrap();
+    match cpu.step() {
+        Err(EmulationError::MemoryRange { pc: 0x0003, opcode: 0xc5, addr: 0xffff, cycles: 11 }) => {}
+        other => panic!("expected a trapped access, got {:?}", other),
+    }
+    assert_eq!(cpu.sp(), 0xfffe);
+}
+
+#[test]
+fn trapped_instruction_keeps_frame_timing() {
+    let mut cpu = Cpu8080::with_memory(small_map(UnmappedPolicy::Trap));
+    // EI ; LDA $4000
+    cpu.memory_mut().load(0, &[0xfb, 0x3a, 0x00, 0x40]);
+    let mut scheduler = Scheduler::new(100, vec![(10, 1)]);
+    scheduler.step(&mut cpu).unwrap();
+    assert!(matches!(scheduler.step(&mut cpu), Err(EmulationError::MemoryRange { cycles: 13, .. })));
+    assert_eq!((scheduler.total_cycles(), scheduler.frame_cycle()), (17, 17));
+    assert!(cpu.interrupt_pending());
+}
+
+#[test]
+fn stack_and_operands_wrap_around() {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    // LXI H,$1234 at $fffe, the operand wraps to $0000
+    cpu.memory_mut().load(0xfffe, &[0x21, 0x34]);
+    cpu.memory_mut().load(0x0000, &[0x12, 0xc5]);
+    cpu.set_pc(0xfffe);
+    cpu.set_sp(0x0001);
+    cpu.step().unwrap();
+    assert_eq!((cpu.hl(), cpu.pc()), (0x1234, 0x0001));
+    // PUSH B with SP at $0001 writes $0000 and $ffff
+    cpu.state_mut().b = 0xab;
+    cpu.step().unwrap();
+    assert_eq!((cpu.sp(), cpu.memory().peek(0x0000)), (0xffff, 0xab));
+}
+
+#[test]
+fn rom_ignores_writes() {
+    let mut map = MemoryMap::unmapped();
+    map.map_rom(0x0000, 0x1000);
+    map.map_ram(0x1000, 0x1000);
+    map.load(0x0000, &[0x12, 0x34]);
+    map.write(0x0000, 0x99);
+    map.write(0x1000, 0x99);
+    assert_eq!((map.read(0x0000), map.read(0x0001), map.read(0x1000)), (0x12, 0x34, 0x99));
+    assert_eq!(map.take_fault(), None);
+}
+
+#[test]
+fn space_invaders_mirrors_above_4000() {
+    let mut map = MemoryMap::space_invaders();
+    map.load(0x0000, &[0xc3, 0x00, 0x18]);
+    map.write(0x0000, 0x00);
+    assert_eq!(map.read(0x0000), 0xc3);
+
+    // work RAM and video RAM repeat every 16 KiB
+    map.write(0x2000, 0x11);
+    map.write(0x3fff, 0x22);
+    for base in [0x4000u16, 0x8000, 0xc000] {
+        assert_eq!(map.read(base + 0x2000), 0x11, "{:04x}", base);
+        assert_eq!(map.read(base + 0x3fff), 0x22, "{:04x}", base);
+        // the ROM too, and it stays read-only through the mirror
+        assert_eq!(map.read(base), 0xc3, "{:04x}", base);
+        map.write(base, 0x00);
+        assert_eq!(map.read(0x0000), 0xc3);
+    }
+    // writes through a mirror land in the RAM below
+    map.write(0x6400, 0x33);
+    map.write(0xe401, 0x44);
+    assert_eq!((map.read(0x2400), map.read(0x2401)), (0x33, 0x44));
+}
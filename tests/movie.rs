@@ -0,0 +1,105 @@
+use rust_8080::invaders::{self, DipSwitches, InvadersIo};
+use rust_8080::movie::{self, Movie, MovieError, Start};
+use rust_8080::rom::Rom;
+use rust_8080::savestate;
+use rust_8080::timing::Scheduler;
+use rust_8080::{Cpu8080, MemoryMap};
+
+/// Adds input port 1 to $2000 in a loop, with interrupt handlers that only return.
+const PROGRAM: [u8; 0x2e] = [
+    0x31, 0x00, 0x24, // LXI SP,$2400
+    0xc3, 0x20, 0x00, // JMP $0020
+    0, 0, //
+    0xfb, 0xc9, // RST 1: EI ; RET
+    0, 0, 0, 0, 0, 0, //
+    0xfb, 0xc9, // RST 2: EI ; RET
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, //
+    0xfb, // EI
+    0xdb, 0x01, // IN 1
+    0x47, // MOV B,A
+    0x3a, 0x00, 0x20, // LDA $2000
+    0x80, // ADD B
+    0x32, 0x00, 0x20, // STA $2000
+    0xc3, 0x21, 0x00, // JMP $0021
+];
+
+type Machine = (Cpu8080<MemoryMap, InvadersIo>, Scheduler);
+
+fn rom() -> Rom {
+    Rom {
+        addr: 0,
+        data: PROGRAM.to_vec(),
+    }
+}
+
+fn power_on() -> Machine {
+    let mut cpu = Cpu8080::with_devices(MemoryMap::space_invaders(), InvadersIo::new());
+    cpu.memory_mut().load(0, &PROGRAM);
+    (cpu, invaders::scheduler())
+}
+
+fn play(machine: &mut Machine, frames: &[[u8; 2]]) -> u32 {
+    let (cpu, scheduler) = machine;
+    for buttons in frames {
+        cpu.io_mut().set_buttons(*buttons);
+        scheduler.run_frame(cpu).unwrap();
+    }
+    movie::checksum(&[&*cpu, &*scheduler])
+}
+
+fn record(frames: usize) -> Movie {
+    let mut movie = Movie::new(savestate::rom_id(&[rom()]), Start::PowerOn(DipSwitches::default()));
+    movie.frames = (0..frames).map(|n| [(n * 37 % 128) as u8, (n * 11 % 64) as u8]).collect();
+    movie.checksum = play(&mut power_on(), &movie.frames);
+    movie
+}
+
+#[test]
+fn replay_matches_recording() {
+    let movie = Movie::decode(&record(120).encode()).unwrap();
+    assert_eq!(play(&mut power_on(), &movie.frames), movie.checksum);
+
+    let mut tampered = movie.frames.clone();
+    tampered[60][0] ^= 0x01;
+    assert_ne!(play(&mut power_on(), &tampered), movie.checksum);
+}
+
+#[test]
+fn round_trip_both_starts() {
+    let movie = record(10);
+    assert_eq!(Movie::decode(&movie.encode()).unwrap(), movie);
+
+    let dips = DipSwitches {
+        lives: 5,
+        bonus_life_at_1000: true,
+        coin_info: false,
+    };
+    let mut with_dips = movie.clone();
+    with_dips.start = Start::PowerOn(dips);
+    assert_eq!(Movie::decode(&with_dips.encode()).unwrap(), with_dips);
+
+    let mut machine = power_on();
+    play(&mut machine, &movie.frames);
+    let state = savestate::encode(movie.rom_id, &[&machine.0, &machine.1]);
+    let mut from_state = movie.clone();
+    from_state.start = Start::State(state);
+    assert_eq!(Movie::decode(&from_state.encode()).unwrap(), from_state);
+}
+
+#[test]
+fn refuses_truncated_and_foreign_movies() {
+    let data = record(10).encode();
+    for len in 0..data.len() {
+        assert!(Movie::decode(&data[..len]).is_err(), "accepted {} of {} bytes", len, data.len());
+    }
+    // the header is fine, so trailing bytes mean damage rather than a foreign file
+    let mut longer = data.clone();
+    longer.push(0);
+    assert!(matches!(Movie::decode(&longer), Err(MovieError::Corrupt)));
+
+    let path = std::env::temp_dir().join(format!("rust_8080-movie-{}.mov", std::process::id()));
+    record(10).save(&path).unwrap();
+    let result = Movie::load(&path, 0xdead_beef);
+    std::fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(MovieError::WrongRom { expected: 0xdead_beef, .. })));
+}
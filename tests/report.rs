@@ -0,0 +1,54 @@
+use rust_8080::report::{hexdump, intel_hex};
+use rust_8080::MemoryMap;
+
+fn memory(addr: u16, bytes: &[u8]) -> MemoryMap {
+    let mut memory = MemoryMap::new();
+    memory.load(addr, bytes);
+    memory
+}
+
+/// The address column of every line, `*` for collapsed runs.
+fn addresses(dump: &str) -> Vec<&str> {
+    dump.lines().map(|line| line.split(' ').next().unwrap()).collect()
+}
+
+#[test]
+fn hexdump_collapses_repeated_lines() {
+    let memory = memory(0x0000, b"Hello, 8080!");
+    let dump = hexdump(&memory, 0x0000, 0x60, &[], true);
+    assert_eq!(addresses(&dump), ["0000", "0010", "*", "0050"]);
+    assert!(dump.starts_with("0000  48 65 6c 6c 6f 2c 20 38 30 38 30 21 00 00 00 00  Hello, 8080!....\n"));
+
+    // a marked line breaks the run, the lines after it collapse again
+    let dump = hexdump(&memory, 0x0000, 0x60, &[(0x0034, "SP")], true);
+    assert_eq!(addresses(&dump), ["0000", "0010", "*", "0030", "*", "0050"]);
+    let marked = dump.lines().find(|line| line.starts_with("0030")).unwrap();
+    assert!(marked.ends_with("................  <- SP"), "{}", marked);
+
+    let dump = hexdump(&memory, 0x0000, 0x60, &[], false);
+    assert_eq!(addresses(&dump), ["0000", "0010", "0020", "0030", "0040", "0050"]);
+    // a short last line
+    let dump = hexdump(&memory, 0x0008, 0x0c, &[], true);
+    assert_eq!(dump.lines().collect::<Vec<_>>(), ["0008  30 38 30 21 00 00 00 00 00 00 00 00              080!........"]);
+}
+
+#[test]
+fn intel_hex_checksums() {
+    let bytes = [
+        0x21, 0x46, 0x01, 0x36, 0x01, 0x21, 0x47, 0x01, 0x36, 0x00, 0x7e, 0xfe, 0x09, 0xd2, 0x19, 0x01, 0x21, 0x46,
+        0x01,
+    ];
+    let memory = memory(0x0100, &bytes);
+    assert_eq!(
+        intel_hex(&memory, 0x0100, bytes.len() as u32),
+        ":10010000214601360121470136007EFE09D2190140\n:0301100021460184\n:00000001FF\n"
+    );
+    assert_eq!(intel_hex(&memory, 0x0100, 0), ":00000001FF\n");
+    // every record sums to zero
+    for record in intel_hex(&memory, 0x00f8, 0x30).lines() {
+        let sum = (1..record.len())
+            .step_by(2)
+            .fold(0u8, |sum, i| sum.wrapping_add(u8::from_str_radix(&record[i..i + 2], 16).unwrap()));
+        assert_eq!(sum, 0, "{}", record);
+    }
+}
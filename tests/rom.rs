@@ -0,0 +1,40 @@
+use rust_8080::rom::{self, RomError, SPACE_INVADERS};
+use std::fs;
+use std::path::PathBuf;
+
+/// An empty directory of its own for every test.
+fn empty_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rust_8080-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn missing_names_every_absent_file() {
+    let dir = empty_dir("roms-missing");
+    fs::write(dir.join("invaders.g"), [0; 0x800]).unwrap();
+    let error = rom::load_set_from_dir(&dir, &SPACE_INVADERS).err().unwrap();
+    match &error {
+        RomError::Missing { source, files } => {
+            assert_eq!(source, &dir);
+            assert_eq!(files, &["invaders.h", "invaders.f", "invaders.e"]);
+        }
+        other => panic!("expected missing files, got {:?}", other),
+    }
+    assert_eq!(
+        error.to_string(),
+        format!("ROM set in {} is incomplete, missing: invaders.h, invaders.f, invaders.e", dir.display())
+    );
+
+    for file in ["invaders.h", "invaders.f", "invaders.e"] {
+        fs::write(dir.join(file), [0; 0x800]).unwrap();
+    }
+    let roms = rom::load_set_from_dir(&dir, &SPACE_INVADERS).unwrap();
+    assert_eq!(roms.iter().map(|rom| rom.addr).collect::<Vec<_>>(), [0x0000, 0x0800, 0x1000, 0x1800]);
+
+    fs::write(dir.join("invaders.f"), [0; 0x7ff]).unwrap();
+    let error = rom::load_set_from_dir(&dir, &SPACE_INVADERS).err().unwrap();
+    assert!(matches!(error, RomError::WrongSize { expected: 0x800, actual: 0x7ff, .. }), "{:?}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,48 @@
+use rust_8080::screen::{self, HEIGHT, NUM_PIXELS, VRAM_START, WIDTH};
+use rust_8080::{Bus, MemoryMap};
+use std::fs::{self, File};
+
+/// Index of the pixel at `x`, `y` from the top left of the rotated picture.
+fn pixel(x: usize, y: usize) -> usize {
+    y * WIDTH + x
+}
+
+#[test]
+fn render_rotates_video_ram() {
+    let mut memory = MemoryMap::new();
+    let blank = screen::render(&memory);
+    assert_eq!(blank.len(), NUM_PIXELS);
+    assert!(blank.iter().all(|&p| p == 0));
+
+    // bit 0 of the first byte is the bottom left corner
+    memory.write(VRAM_START, 0x01);
+    // bit 7 of the last byte of column 5 is at its top
+    memory.write(VRAM_START + 5 * 0x20 + 0x1f, 0x80);
+    // bit 3 of byte 2 of column 100 is 2 * 8 + 3 pixels above the bottom
+    memory.write(VRAM_START + 100 * 0x20 + 2, 0x08);
+    let lit: Vec<usize> = screen::render(&memory)
+        .iter()
+        .enumerate()
+        .filter(|(_, &p)| p == 0x00ff_ffff)
+        .map(|(i, _)| i)
+        .collect();
+    assert_eq!(lit, [pixel(5, 0), pixel(100, HEIGHT - 1 - 19), pixel(0, HEIGHT - 1)]);
+}
+
+#[test]
+fn png_is_224_by_256() {
+    let mut pixels = vec![0; NUM_PIXELS];
+    pixels[pixel(3, 0)] = 0x00ff_ffff;
+    let path = std::env::temp_dir().join(format!("rust_8080-screen-{}.png", std::process::id()));
+    screen::write_png(&path, &pixels).unwrap();
+    let decoder = png::Decoder::new(File::open(&path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (WIDTH as u32, HEIGHT as u32));
+    assert_eq!((info.color_type, info.bit_depth), (png::ColorType::Rgb, png::BitDepth::Eight));
+    let mut data = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(&data[..12], [0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff]);
+    assert!(data[12..].iter().all(|&b| b == 0));
+}
@@ -0,0 +1,98 @@
+use rust_8080::singlestep::{self, Report, TestCase};
+use std::fs;
+use std::path::PathBuf;
+
+const CASES: &str = r#"[
+    {"name": "3e 0000",
+     "initial": {"pc": 256, "sp": 0, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+                 "ram": [[256, 62], [257, 66]]},
+     "final": {"pc": 258, "sp": 0, "a": 66, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+               "ram": [[256, 62], [257, 66]]},
+     "cycles": 7},
+    {"name": "80 0000",
+     "initial": {"pc": 0, "sp": 0, "a": 58, "b": 198, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+                 "ram": [[0, 128]]},
+     "final": {"pc": 1, "sp": 0, "a": 0, "b": 198, "c": 0, "d": 0, "e": 0, "f": 87, "h": 0, "l": 0,
+               "ram": [[0, 128]]},
+     "cycles": [[0, 128, "r"], [0, null, ""], [0, null, ""], [0, null, ""]]},
+    {"name": "d3 0000",
+     "initial": {"pc": 0, "sp": 0, "a": 171, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+                 "ram": [[0, 211], [1, 7]]},
+     "final": {"pc": 2, "sp": 0, "a": 171, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 0, "l": 0,
+               "ram": []},
+     "cycles": 10,
+     "ports": [[1799, 171, "w"]]},
+    {"name": "77 0000",
+     "initial": {"pc": 0, "sp": 0, "a": 9, "b": 0, "c": 0, "d": 0, "e": 0, "f": 2, "h": 32, "l": 0,
+                 "ram": [[0, 119], [8192, 0]]},
+     "final": {"pc": 1, "sp": 0, "a": 9, "b": 0, "c": 0, "d": 0, "e": 0, "f": 3, "h": 32, "l": 0,
+               "ram": [[8192, 10]]},
+     "cycles": 5}
+]"#;
+
+fn cases() -> Vec<TestCase> {
+    serde_json::from_str(CASES).unwrap()
+}
+
+#[test]
+fn passing_cases() {
+    let cases = cases();
+    for case in &cases[..3] {
+        assert_eq!(singlestep::run(case), Vec::new(), "{}", case.name);
+    }
+}
+
+#[test]
+fn reports_every_differing_field() {
+    // MOV M,A expecting a carry, the wrong byte and one cycle too few
+    let case = &cases()[3];
+    let fields: Vec<String> = singlestep::run(case).into_iter().map(|mismatch| mismatch.field).collect();
+    assert_eq!(fields, ["cycles", "f", "ram[2000]"]);
+
+    let mut report = Report::new();
+    for case in &cases() {
+        report.run(case);
+    }
+    assert_eq!(report.tests(), 4);
+    assert_eq!(report.failures(), 1);
+    assert_eq!(report.failed_opcodes(), [0x77]);
+    let text = report.to_string();
+    assert!(text.contains("opcode 77: 1 of 1 failed"), "{}", text);
+    assert!(text.contains("f: expected 03 ----C, found 02 -----"), "{}", text);
+}
+
+/// Vectors in the SingleStepTests layout, one JSON file per opcode in
+/// tests/singlestep. They are not part of the repository, put them there and run
+/// `cargo test -- --ignored`.
+#[test]
+#[ignore = "needs tests/singlestep/*.json"]
+fn vectors() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/singlestep");
+    let entries = fs::read_dir(&dir).unwrap_or_else(|e| panic!("{}: {}", dir.display(), e));
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no vectors in {}", dir.display());
+    let mut report = Report::new();
+    for file in &files {
+        for case in &singlestep::load(file).unwrap() {
+            report.run(case);
+        }
+    }
+    println!("{}", report);
+    assert_eq!(report.failures(), 0, "{}", report);
+}
+
+/// Conditional CALL and RET timings, XTHL, SPHL, PCHL and the undocumented aliases.
+#[test]
+fn opcode_cases() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/opcodes.json");
+    let mut report = Report::new();
+    for case in &singlestep::load(&path).unwrap() {
+        report.run(case);
+    }
+    assert_eq!(report.tests(), 14);
+    assert_eq!(report.failures(), 0, "{}", report);
+}
@@ -0,0 +1,94 @@
+use rust_8080::invaders::{self, scanline_cycle, CYCLES_PER_FRAME, END_OF_SCREEN_LINE, MID_SCREEN_LINE};
+use rust_8080::savestate::{Input, Snapshot, StateError};
+use rust_8080::timing::Scheduler;
+use rust_8080::{Cpu8080, MemoryMap};
+
+/// Memory filled with `byte`. Interrupts stay disabled, so the last request stays pending.
+fn filled(byte: u8) -> Cpu8080 {
+    let mut cpu = Cpu8080::with_memory(MemoryMap::new());
+    cpu.memory_mut().load(0, &vec![byte; 0x10000]);
+    cpu
+}
+
+/// The RST the scheduler raised last, if any.
+fn requested(cpu: &Cpu8080) -> Option<u8> {
+    cpu.state().interrupt_request.map(|instruction| (instruction[0] >> 3) & 7)
+}
+
+#[test]
+fn invaders_interrupts_at_their_scanlines() {
+    // NOPs of 4 cycles
+    let mut cpu = filled(0x00);
+    let mut scheduler = invaders::scheduler();
+    let mut raised = Vec::new();
+    while !scheduler.take_frame_done() {
+        let before = scheduler.total_cycles();
+        let rst = requested(&cpu);
+        scheduler.step(&mut cpu).unwrap();
+        if requested(&cpu) != rst {
+            raised.push((requested(&cpu).unwrap(), before, scheduler.total_cycles()));
+        }
+    }
+    // each interrupt is raised by the instruction that reaches its cycle
+    let points = [(1, scanline_cycle(MID_SCREEN_LINE)), (2, scanline_cycle(END_OF_SCREEN_LINE))];
+    assert_eq!(raised.len(), 2);
+    for ((rst, before, after), (expected, at)) in raised.into_iter().zip(points) {
+        assert_eq!(rst, expected);
+        assert!(before < u64::from(at) && after >= u64::from(at), "RST {} at {}-{}, due at {}", rst, before, after, at);
+    }
+    assert_eq!((scheduler.frames(), scheduler.frame_cycle()), (1, 0));
+    assert_eq!(scheduler.total_cycles(), u64::from(CYCLES_PER_FRAME));
+}
+
+#[test]
+fn frame_wraps_and_carries_the_excess() {
+    // MVI A,$3E of 7 cycles each
+    let mut cpu = filled(0x3e);
+    let mut scheduler = Scheduler::new(100, vec![(70, 2), (35, 1)]);
+    let mut steps = 0;
+    while !scheduler.take_frame_done() {
+        scheduler.step(&mut cpu).unwrap();
+        steps += 1;
+        match steps {
+            // 35 cycles
+            5 => assert_eq!(requested(&cpu), Some(1)),
+            // 70 cycles
+            10 => assert_eq!(requested(&cpu), Some(2)),
+            _ => {}
+        }
+    }
+    // 15 instructions take 105 cycles
+    assert_eq!((steps, scheduler.frames(), scheduler.frame_cycle()), (15, 1, 5));
+    assert!(!scheduler.take_frame_done());
+
+    // the second frame starts 5 cycles in and ends one instruction earlier
+    let mut steps = 0;
+    while !scheduler.take_frame_done() {
+        scheduler.step(&mut cpu).unwrap();
+        steps += 1;
+        if steps == 5 {
+            assert_eq!(requested(&cpu), Some(1));
+        }
+    }
+    assert_eq!((steps, scheduler.frames(), scheduler.frame_cycle()), (14, 2, 3));
+    assert_eq!(scheduler.total_cycles(), 29 * 7);
+}
+
+#[test]
+fn restore_rejects_a_frame_cycle_past_the_frame() {
+    let scheduler = Scheduler::new(100, vec![(50, 1)]);
+    let mut state = Vec::new();
+    scheduler.save(&mut state);
+    let mut restored = Scheduler::new(100, vec![(50, 1)]);
+    assert!(restored.restore(&mut Input::new(&state)).is_ok());
+
+    for frame_cycle in [99u32, 100, u32::MAX] {
+        state[4..8].copy_from_slice(&frame_cycle.to_le_bytes());
+        let result = restored.restore(&mut Input::new(&state));
+        assert_eq!(result.is_ok(), frame_cycle < 100, "{}", frame_cycle);
+        if frame_cycle >= 100 {
+            assert!(matches!(result, Err(StateError::Corrupt)));
+        }
+    }
+    assert_eq!(restored.frame_cycle(), 99);
+}
@@ -0,0 +1,92 @@
+use rust_8080::trace::{TraceFilter, TraceReader, MAGIC};
+use rust_8080::Instructioninfo;
+use std::io;
+
+fn info(instr_n: u64) -> Instructioninfo {
+    Instructioninfo {
+        instr_n,
+        cycles: 0x0123_4567_89ab_cdef,
+        opcode: 0xcd,
+        operands: [0x34, 0x12],
+        pc: 0xfedc,
+        sp: 0x23fe,
+        a: 0x01,
+        b: 0x02,
+        c: 0x03,
+        d: 0x04,
+        e: 0x05,
+        h: 0x06,
+        l: 0x07,
+        flags: 0xd7,
+        int_enable: false,
+        halted: true,
+        interrupt: true,
+    }
+}
+
+#[test]
+fn record_round_trip() {
+    let original = info(u64::MAX - 1);
+    let record = original.encode();
+    assert_eq!(record.len(), Instructioninfo::ENCODED_LEN);
+    assert_eq!(record[31], 0b110);
+    assert_eq!(Instructioninfo::decode(&record), original);
+
+    let flipped = Instructioninfo {
+        int_enable: true,
+        halted: false,
+        interrupt: false,
+        flags: 0x02,
+        ..original
+    };
+    assert_eq!(Instructioninfo::decode(&flipped.encode()), flipped);
+}
+
+#[test]
+fn filter_criteria() {
+    assert!(TraceFilter::default().matches(u64::MAX, 0xffff, 0xff));
+
+    let window = TraceFilter {
+        window: Some(100..=200),
+        ..TraceFilter::default()
+    };
+    assert!(!window.matches(99, 0, 0));
+    assert!(window.matches(100, 0, 0));
+    assert!(window.matches(200, 0, 0));
+    assert!(!window.matches(201, 0, 0));
+
+    let pc = TraceFilter {
+        pc: Some(0x1000..=0x10ff),
+        ..TraceFilter::default()
+    };
+    assert!(pc.matches(0, 0x1000, 0) && pc.matches(0, 0x10ff, 0));
+    assert!(!pc.matches(0, 0x0fff, 0) && !pc.matches(0, 0x1100, 0));
+
+    let all = TraceFilter {
+        opcodes: vec![0xcd, 0xc9],
+        ..pc
+    };
+    assert!(all.matches(0, 0x1000, 0xc9));
+    assert!(!all.matches(0, 0x1000, 0x00));
+    assert!(!all.matches(0, 0x2000, 0xcd));
+}
+
+#[test]
+fn reader_rejects_truncated_records() {
+    let mut trace = MAGIC.to_vec();
+    trace.extend_from_slice(&info(0).encode());
+    trace.extend_from_slice(&info(1).encode());
+
+    let records: Vec<_> = TraceReader::new(&trace[..]).unwrap().map(Result::unwrap).collect();
+    assert_eq!(records, [info(0), info(1)]);
+
+    trace.truncate(MAGIC.len() + Instructioninfo::ENCODED_LEN + 10);
+    let mut reader = TraceReader::new(&trace[..]).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap(), info(0));
+    let error = reader.next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+    let error = TraceReader::new(&b"8080TRC\x02"[..]).err().unwrap();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(TraceReader::new(&MAGIC[..4]).is_err());
+}
@@ -0,0 +1,106 @@
+use rust_8080::debugger::Debugger;
+use rust_8080::watch::{AccessKind, MemoryAccess, Recorder, Watch, Watchpoint};
+use rust_8080::{Cpu8080, MemoryMap};
+
+fn parse(spec: &str) -> Result<Watchpoint, String> {
+    Watchpoint::parse(spec, |text| u64::from_str_radix(text, 16).map_err(|e| e.to_string()))
+}
+
+fn access(kind: AccessKind, addr: u16, new: u8) -> MemoryAccess {
+    MemoryAccess { kind, addr, old: 0, new }
+}
+
+#[test]
+fn parse_watchpoints() {
+    let watchpoint = parse("2400").unwrap();
+    assert_eq!(
+        watchpoint,
+        Watchpoint {
+            range: 0x2400..=0x2400,
+            watch: Watch::Write,
+            value: None,
+            log_only: false,
+        }
+    );
+    assert_eq!(watchpoint.to_string(), "2400 w");
+
+    let watchpoint = parse("2400-3fff, r =ff log").unwrap();
+    assert_eq!((watchpoint.range.clone(), watchpoint.watch), (0x2400..=0x3fff, Watch::Read));
+    assert_eq!((watchpoint.value, watchpoint.log_only), (Some(0xff), true));
+    assert_eq!(watchpoint.to_string(), "2400-3fff r =ff log");
+    assert_eq!(parse(&watchpoint.to_string()), Ok(watchpoint));
+    assert_eq!(parse("20c0,rw").unwrap().watch, Watch::ReadWrite);
+
+    assert_eq!(parse(" ").unwrap_err(), "missing watchpoint address");
+    assert_eq!(parse("3fff-2400").unwrap_err(), "watch range 3fff-2400 is empty");
+    assert_eq!(parse("10000").unwrap_err(), "address 0x10000 is above $ffff");
+    assert_eq!(parse("2400 =100").unwrap_err(), "0x100 is not a byte");
+    assert_eq!(parse("2400 x").unwrap_err(), "unknown watchpoint option 'x'");
+    assert!(parse("24g0").is_err());
+}
+
+#[test]
+fn match_kind_range_and_value() {
+    let write = parse("2400-24ff").unwrap();
+    assert!(write.matches(&access(AccessKind::Write, 0x2400, 0x00)));
+    assert!(write.matches(&access(AccessKind::Write, 0x24ff, 0x00)));
+    assert!(!write.matches(&access(AccessKind::Write, 0x2500, 0x00)));
+    assert!(!write.matches(&access(AccessKind::Read, 0x2400, 0x00)));
+
+    let read = parse("2400 r =42").unwrap();
+    assert!(read.matches(&access(AccessKind::Read, 0x2400, 0x42)));
+    assert!(!read.matches(&access(AccessKind::Read, 0x2400, 0x43)));
+    assert!(!read.matches(&access(AccessKind::Write, 0x2400, 0x42)));
+
+    let both = parse("2400 rw").unwrap();
+    assert!(both.matches(&access(AccessKind::Read, 0x2400, 0x00)));
+    assert!(both.matches(&access(AccessKind::Write, 0x2400, 0x00)));
+}
+
+/// Steps the CPU with the accesses recorded and checked, returning what the
+/// debugger logged and whether it stops before the next instruction.
+fn step(cpu: &mut Cpu8080<Recorder<MemoryMap>>, debugger: &mut Debugger) -> (String, bool) {
+    cpu.memory_mut().set_enabled(true);
+    let (pc, bytes) = (cpu.pc(), cpu.next_instruction());
+    cpu.step().unwrap();
+    let accesses = cpu.memory_mut().take_accesses();
+    let mut log = Vec::new();
+    debugger.check_accesses(pc, bytes, &accesses, &mut log).unwrap();
+    (String::from_utf8(log).unwrap(), debugger.should_break(cpu.pc()))
+}
+
+#[test]
+fn check_accesses_skips_fetches() {
+    let mut cpu = Cpu8080::with_memory(Recorder::new(MemoryMap::new()));
+    cpu.memory_mut().inner_mut().load(
+        0,
+        &[
+            0x21, 0x01, 0x00, // LXI H,$0001
+            0x7e, // MOV A,M
+            0x3e, 0x42, // MVI A,$42
+            0x32, 0x00, 0x20, // STA $2000
+        ],
+    );
+    let mut debugger = Debugger::new(false);
+    debugger.add_watchpoint(parse("0001 r").unwrap());
+    debugger.add_watchpoint(parse("2000 w =42 log").unwrap());
+    debugger.add_watchpoint(parse("2000 w =43").unwrap());
+
+    // fetching the operand byte at $0001 is no data read
+    assert_eq!(step(&mut cpu, &mut debugger), (String::new(), false));
+    let (log, paused) = step(&mut cpu, &mut debugger);
+    assert!(log.starts_with("watch 0001 r: read 0001 = 01 at 0003"), "{}", log);
+    assert_eq!(log.lines().count(), 1);
+    assert!(paused);
+    assert_eq!(debugger.take_watch_hit(), Some((Watch::Read, 0x0001)));
+    assert_eq!(debugger.take_watch_hit(), None);
+
+    debugger.resume();
+    assert_eq!(step(&mut cpu, &mut debugger), (String::new(), false));
+    // the log only watchpoint reports the write without stopping, the other value does not match
+    let (log, paused) = step(&mut cpu, &mut debugger);
+    assert!(log.starts_with("watch 2000 w =42 log: write 2000 00 -> 42 at 0006"), "{}", log);
+    assert_eq!(log.lines().count(), 1);
+    assert!(!paused);
+    assert_eq!(debugger.take_watch_hit(), None);
+}